@@ -0,0 +1,192 @@
+use anyhow::{Context, Result};
+use std::{
+    ffi::OsStr,
+    io::{Read, Seek},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::decompress::Compression;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    Tar,
+    Zip,
+}
+
+impl ArchiveKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension().and_then(OsStr::to_str)?.to_ascii_lowercase();
+        match ext.as_str() {
+            "tar" | "tgz" | "tbz2" | "tzst" => Some(ArchiveKind::Tar),
+            "zip" => Some(ArchiveKind::Zip),
+            // page.tar.gz and friends, the tar reader decompresses by magic
+            ext if Compression::from_extension(ext).is_some() => {
+                match ArchiveKind::from_path(Path::new(path.file_stem()?)) {
+                    Some(ArchiveKind::Tar) => Some(ArchiveKind::Tar),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Member {
+    pub path: PathBuf,
+    pub modified: Option<u64>,
+    pub data: Vec<u8>,
+}
+
+// Normalizes member paths (./foo/bar.html -> foo/bar.html) and drops hidden files and
+// directories the same way the directory walker does.
+fn member_path(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => {
+                if part.to_str().is_some_and(|v| v.starts_with('.')) {
+                    return None;
+                }
+                out.push(part)
+            }
+            Component::CurDir => {}
+            // absolute paths and .. are not something we want to trust
+            _ => return None,
+        }
+    }
+    Some(out).filter(|v| !v.as_os_str().is_empty())
+}
+
+// tar entries borrow the archive, so read it on its own thread and hand members over a
+// small channel. Keeps memory bounded to a few members even for huge archives.
+pub fn tar_members<F, R>(open: F) -> impl Iterator<Item = Result<Member>>
+where
+    F: FnOnce() -> Result<R> + Send + 'static,
+    R: Read,
+{
+    let (tx, rx) = crossbeam_channel::bounded(4);
+    std::thread::spawn(move || {
+        let res = (|| -> Result<()> {
+            let mut archive = tar::Archive::new(open()?);
+            for entry in archive.entries().context("failed to read tar archive")? {
+                let mut entry = entry.context("failed to read tar entry")?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let path = match member_path(&entry.path()?) {
+                    Some(p) => p,
+                    None => continue,
+                };
+                let modified = entry.header().mtime().ok();
+                let mut data = member_buffer(entry.size());
+                entry
+                    .read_to_end(&mut data)
+                    .with_context(|| format!("failed to read tar entry {}", path.display()))?;
+                if tx.send(Ok(Member { path, modified, data })).is_err() {
+                    // nobody is listening anymore
+                    return Ok(());
+                }
+            }
+            Ok(())
+        })();
+        if let Err(e) = res {
+            let _ = tx.send(Err(e));
+        }
+    });
+    rx.into_iter()
+}
+
+// The size comes from the archive header, a broken one must not make us allocate gigabytes
+// up front. Bigger members still get read, the buffer just grows as it goes.
+const MAX_PREALLOCATION: u64 = 1 << 20;
+
+fn member_buffer(size: u64) -> Vec<u8> {
+    Vec::with_capacity(size.min(MAX_PREALLOCATION) as usize)
+}
+
+pub fn zip_members<R>(reader: R) -> Result<impl Iterator<Item = Result<Member>>>
+where
+    R: Read + Seek,
+{
+    let mut archive = zip::ZipArchive::new(reader).context("failed to read zip archive")?;
+    Ok((0..archive.len()).filter_map(move |i| {
+        let mut file = match archive.by_index(i) {
+            Ok(f) => f,
+            Err(e) => return Some(Err(anyhow::Error::from(e).context("failed to read zip entry"))),
+        };
+        if !file.is_file() {
+            return None;
+        }
+        let path = member_path(&file.enclosed_name()?)?;
+        // zip timestamps have no timezone, treat them as UTC
+        let modified = file.last_modified().and_then(|dt| {
+            chrono::NaiveDate::from_ymd_opt(dt.year().into(), dt.month().into(), dt.day().into())?
+                .and_hms_opt(dt.hour().into(), dt.minute().into(), dt.second().into())
+                .map(|v| v.and_utc().timestamp() as u64)
+        });
+        let mut data = member_buffer(file.size());
+        Some(
+            file.read_to_end(&mut data)
+                .with_context(|| format!("failed to read zip entry {}", path.display()))
+                .map(|_| Member { path, modified, data }),
+        )
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn archive_kind_from_path() {
+        assert_eq!(ArchiveKind::from_path(Path::new("a.tar")), Some(ArchiveKind::Tar));
+        assert_eq!(ArchiveKind::from_path(Path::new("a.tar.gz")), Some(ArchiveKind::Tar));
+        assert_eq!(ArchiveKind::from_path(Path::new("a.tgz")), Some(ArchiveKind::Tar));
+        assert_eq!(ArchiveKind::from_path(Path::new("a.zip")), Some(ArchiveKind::Zip));
+        assert_eq!(ArchiveKind::from_path(Path::new("a.html.gz")), None);
+        assert_eq!(ArchiveKind::from_path(Path::new("a.html")), None);
+    }
+
+    #[test]
+    fn reads_tar_members() {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, body) in &[("./pages/a.html", "a"), ("pages/.hidden.html", "h"), ("b.html", "b")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(body.len() as u64);
+            header.set_mtime(1610000000);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, body.as_bytes()).unwrap();
+        }
+        let data = builder.into_inner().unwrap();
+        let members = tar_members(move || Ok(Cursor::new(data)))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].path, PathBuf::from("pages/a.html"));
+        assert_eq!(members[0].modified, Some(1610000000));
+        assert_eq!(members[0].data, b"a");
+        assert_eq!(members[1].path, PathBuf::from("b.html"));
+    }
+
+    #[test]
+    fn reads_zip_members() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .last_modified_time(zip::DateTime::from_date_and_time(2021, 1, 7, 6, 13, 20).unwrap());
+        writer.add_directory("pages/", options).unwrap();
+        writer.start_file("pages/a.html", options).unwrap();
+        writer.write_all(b"a").unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        let members = zip_members(Cursor::new(data))
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].path, PathBuf::from("pages/a.html"));
+        assert_eq!(members[0].modified, Some(1610000000));
+        assert_eq!(members[0].data, b"a");
+    }
+}
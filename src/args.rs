@@ -1,8 +1,9 @@
 use anyhow::Context;
 use clap::{crate_authors, crate_version, values_t_or_exit, App, Arg, ArgMatches, SubCommand};
-use grep_cli::{is_readable_stdin, is_tty_stdout, stdout, stdout_buffered_line};
+use grep_cli::{is_readable_stdin, is_tty_stderr, is_tty_stdout, stdout, stdout_buffered_line};
 use std::{
     borrow::Borrow,
+    convert::TryFrom,
     ffi::{OsStr, OsString},
     fs::File,
     io::{self, BufRead, BufReader},
@@ -12,6 +13,12 @@ use std::{
     vec,
 };
 use walkdir::{self, DirEntry, WalkDir};
+
+use crate::errorlog::ErrorLogFormat;
+use crate::output::{
+    parse_size, OutputFormat, ParquetCompression, ShardCompression, ShardLimits, TableFormat,
+};
+use crate::validate::RequiredField;
 pub fn parse_args<'a, 'b>() -> clap::App<'a, 'b> {
     App::new("parler-parse")
     .version(crate_version!())
@@ -36,11 +43,59 @@ pub fn parse_args<'a, 'b>() -> clap::App<'a, 'b> {
 .takes_value(true)
 .long("fail-log")
 .number_of_values(1)
+).arg(Arg::with_name("error log format")
+.help("Format of the fail log. json writes one object per failure with the error, its causes and the sha1 when known [default: plain]")
+.takes_value(true)
+.number_of_values(1)
+.long("error-log-format")
+.possible_values(ErrorLogFormat::NAMES)
+.requires("fail file")
 ).arg(Arg::with_name("success file")
-.help("Write successfully processed paths to a file")
+.help("Write successfully processed paths and their sha1 to a file")
 .takes_value(true)
 .number_of_values(1)
 .long("success-log")
+).arg(Arg::with_name("resume")
+.help("Skip paths and documents already listed in this success log and keep appending to it")
+.takes_value(true)
+.number_of_values(1)
+.value_name("success log")
+.long("resume")
+.conflicts_with("success file")
+).arg(Arg::with_name("dedupe")
+.help("Only output the first document with a given sha1, later copies are dropped")
+.long("dedupe")
+).arg(Arg::with_name("dedupe hashes")
+.help("Persist the hashes of output documents in this file and skip documents listed in it")
+.takes_value(true)
+.number_of_values(1)
+.value_name("file")
+.long("dedupe-hashes")
+.requires("dedupe")
+).arg(Arg::with_name("duplicates log")
+.help("Write dropped duplicates to a file, along with the document that was kept")
+.takes_value(true)
+.number_of_values(1)
+.value_name("file")
+.long("duplicates-log")
+.requires("dedupe")
+).arg(Arg::with_name("ordered")
+.help("Write results in input order, with directories walked in file name order, so repeated runs give the same output. Pages are still parsed in parallel")
+.long("ordered")
+).arg(Arg::with_name("watch")
+.help("Keep running after the walk and parse files as they're closed or moved into the walked directories, until interrupted")
+.long("watch")
+.requires("recursive")
+.conflicts_with("path file")
+).arg(Arg::with_name("no progress")
+.help("Don't show progress and the end of run summary on stderr. They are only shown when stderr is a terminal")
+.long("no-progress")
+).arg(Arg::with_name("stats json")
+.help("Write the end of run summary (outcome counts, failures by kind, throughput) to a file as JSON")
+.takes_value(true)
+.number_of_values(1)
+.value_name("file")
+.long("stats-json")
 ).arg(Arg::with_name("path file")
 .help("Read paths from a file")
 .takes_value(true)
@@ -53,22 +108,200 @@ pub fn parse_args<'a, 'b>() -> clap::App<'a, 'b> {
 .number_of_values(1)
 .long("source-label")
 .short("l")
+).arg(Arg::with_name("warc")
+.help("Treat all inputs (including stdin) as WARC files. Files ending in .warc are always read as WARC")
+.long("warc")
+.short("w")
+).arg(Arg::with_name("reference time")
+.help("Resolve relative timestamps (\"3 weeks ago\") against this time instead of the WARC-Date or file modification time. RFC 3339 or unix timestamp")
+.takes_value(true)
+.number_of_values(1)
+.long("reference-time")
+.validator(|v| parse_reference_time(&v).map(|_| ()))
+).arg(Arg::with_name("lenient")
+.help("Keep whatever could be parsed from broken pages instead of failing them. The parts that were left out are listed in __warnings")
+.long("lenient")
+).arg(Arg::with_name("strict")
+.help("Fail pages that are missing any of the --required-fields, even where the parser allows them to be missing")
+.long("strict")
+.conflicts_with("lenient")
+).arg(Arg::with_name("required fields")
+.help("Comma separated rules checked by --strict [default: all of them]")
+.takes_value(true)
+.value_name("rules")
+.long("required-fields")
+.use_delimiter(true)
+.possible_values(RequiredField::NAMES)
+.requires("strict")
+).arg(Arg::with_name("output format")
+.help("Format of the documents written to stdout. es-bulk writes Elasticsearch bulk API requests, merged-posts writes one document per post once all input has been read, mirror writes one file per input to --mirror-dir")
+.takes_value(true)
+.number_of_values(1)
+.long("output-format")
+.possible_values(OutputFormat::NAMES)
+.default_value("json")
+).arg(Arg::with_name("output dir")
+.help("Write the output to numbered shard files (part-00000.ndjson, ...) in this directory instead of stdout, along with a manifest.json listing every shard with its document count and sha1. Implies --compact")
+.takes_value(true)
+.number_of_values(1)
+.value_name("dir")
+.long("output-dir")
+).arg(Arg::with_name("shard documents")
+.help("Start a new shard after this many documents")
+.takes_value(true)
+.number_of_values(1)
+.value_name("N")
+.long("shard-documents")
+.validator(is_positive)
+.requires("output dir")
+).arg(Arg::with_name("shard bytes")
+.help("Start a new shard once it has this many bytes (before compression), e.g. 512M")
+.takes_value(true)
+.number_of_values(1)
+.value_name("size")
+.long("shard-bytes")
+.validator(|v| parse_size(&v).map(|_| ()))
+.requires("output dir")
+).arg(Arg::with_name("shard compression")
+.help("Compress every shard [default: none]")
+.takes_value(true)
+.number_of_values(1)
+.long("shard-compression")
+.possible_values(ShardCompression::NAMES)
+.requires("output dir")
+).arg(Arg::with_name("es index")
+.help("Index name used in the action lines of the es-bulk output format")
+.takes_value(true)
+.number_of_values(1)
+.long("es-index")
+.default_value("parler")
+).arg(Arg::with_name("es split posts")
+.help("Write one es-bulk document per post instead of one per page. Posts are keyed by their post id")
+.long("es-split-posts")
+).arg(Arg::with_name("tables dir")
+.help("Directory for the per table files written by the normalized output format")
+.takes_value(true)
+.number_of_values(1)
+.long("tables-dir")
+.required_if("output format", "normalized")
+).arg(Arg::with_name("mirror dir")
+.help("Directory the mirror output format recreates the input tree in, foo/bar/page.html becomes foo/bar/page.json. Inputs whose json is newer than the html are skipped")
+.takes_value(true)
+.number_of_values(1)
+.value_name("dir")
+.long("mirror-dir")
+.required_if("output format", "mirror")
+).arg(Arg::with_name("table format")
+.help("File format of the normalized tables")
+.takes_value(true)
+.number_of_values(1)
+.long("table-format")
+.possible_values(TableFormat::NAMES)
+.default_value("csv")
+).arg(Arg::with_name("sqlite")
+.help("Also write every page to a SQLite database, created if it doesn't exist. Rows are upserted so the database can be reused across runs")
+.takes_value(true)
+.number_of_values(1)
+.value_name("db")
+.long("sqlite")
+).arg(Arg::with_name("parquet")
+.help("Also write one row per post to a Parquet file")
+.takes_value(true)
+.number_of_values(1)
+.value_name("file")
+.long("parquet")
+).arg(Arg::with_name("parquet compression")
+.help("Compression codec of the Parquet file")
+.takes_value(true)
+.number_of_values(1)
+.long("parquet-compression")
+.possible_values(ParquetCompression::NAMES)
+.default_value("snappy")
+).arg(Arg::with_name("parquet row group pages")
+.help("Number of pages per Parquet row group")
+.takes_value(true)
+.number_of_values(1)
+.long("parquet-row-group-pages")
+.validator(is_positive)
+.default_value("1000")
+).arg(Arg::with_name("jobs")
+.help("Number of parsing threads [default: number of CPUs]")
+.takes_value(true)
+.number_of_values(1)
+.value_name("N")
+.long("jobs")
+.short("j")
+.validator(is_positive)
+).arg(Arg::with_name("queue size")
+.help("Maximum number of parsed pages waiting to be written. Parsing pauses while the queue is full, so a slow output doesn't make the queue grow without bound")
+.takes_value(true)
+.number_of_values(1)
+.value_name("pages")
+.long("queue-size")
+.validator(is_positive)
+.default_value("1000")
+).subcommand(SubCommand::with_name("schema")
+.about("Print the JSON Schema (draft 2020-12) of the output format and exit")
 )
 }
 
+fn is_positive(v: String) -> Result<(), String> {
+    match v.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("expected a positive number, got {:?}", v)),
+    }
+}
+
+pub fn parse_reference_time(v: &str) -> Result<u64, String> {
+    v.parse::<u64>().or_else(|_| {
+        let ts = chrono::DateTime::parse_from_rfc3339(v)
+            .map_err(|e| format!("invalid reference time {:?}: {}", v, e))?
+            .timestamp();
+        u64::try_from(ts).map_err(|_| format!("reference time {:?} is before 1970", v))
+    })
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Configuration {
     paths: Vec<PathBuf>,
     success_path: Option<PathBuf>,
+    resume: bool,
+    dedupe: bool,
+    dedupe_hashes: Option<PathBuf>,
+    duplicates_log: Option<PathBuf>,
     path_file: Option<PathBuf>,
+    ordered: bool,
+    watch: bool,
+    progress: bool,
+    stats_json: Option<PathBuf>,
     fail_path: Option<PathBuf>,
+    error_log_format: ErrorLogFormat,
     source_label: Option<String>,
     compact_output: bool,
     recursive: bool,
     use_stdin: bool,
+    warc: bool,
+    reference_time: Option<u64>,
+    lenient: bool,
+    required_fields: Option<Vec<RequiredField>>,
+    output_format: OutputFormat,
+    output_dir: Option<PathBuf>,
+    shard_limits: ShardLimits,
+    shard_compression: ShardCompression,
+    es_index: String,
+    es_split_posts: bool,
+    tables_dir: Option<PathBuf>,
+    mirror_dir: Option<PathBuf>,
+    table_format: TableFormat,
+    sqlite_path: Option<PathBuf>,
+    parquet_path: Option<PathBuf>,
+    parquet_compression: ParquetCompression,
+    parquet_row_group_pages: usize,
+    jobs: Option<usize>,
+    queue_size: usize,
 }
 
-fn is_hidden(entry: &DirEntry) -> bool {
+pub fn is_hidden(entry: &DirEntry) -> bool {
     entry
         .file_name()
         .to_str()
@@ -81,6 +314,7 @@ impl Configuration {
         &'a self,
     ) -> Box<dyn Iterator<Item = Result<DirEntry, walkdir::Error>> + Send> {
         let recursive = self.recursive;
+        let ordered = self.ordered;
         let paths = self.paths();
         let paths_from_file = match self.path_file() {
             Some(file) => Some(
@@ -108,6 +342,9 @@ impl Configuration {
             if !recursive {
                 wd = wd.max_depth(0);
             }
+            if ordered {
+                wd = wd.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+            }
 
             wd.into_iter().filter_entry(|v| !is_hidden(v))
         };
@@ -143,6 +380,91 @@ impl Configuration {
     pub fn recursive(&self) -> bool {
         self.recursive
     }
+    pub fn warc(&self) -> bool {
+        self.warc
+    }
+    pub fn reference_time(&self) -> Option<u64> {
+        self.reference_time
+    }
+    pub fn lenient(&self) -> bool {
+        self.lenient
+    }
+    /// `None` unless `--strict` was given
+    pub fn required_fields(&self) -> Option<&[RequiredField]> {
+        self.required_fields.as_deref()
+    }
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+    pub fn output_dir(&self) -> Option<&PathBuf> {
+        self.output_dir.as_ref()
+    }
+    pub fn shard_limits(&self) -> ShardLimits {
+        self.shard_limits
+    }
+    pub fn shard_compression(&self) -> ShardCompression {
+        self.shard_compression
+    }
+    pub fn es_index(&self) -> &str {
+        self.es_index.as_str()
+    }
+    pub fn es_split_posts(&self) -> bool {
+        self.es_split_posts
+    }
+    pub fn tables_dir(&self) -> Option<&PathBuf> {
+        self.tables_dir.as_ref()
+    }
+    pub fn mirror_dir(&self) -> Option<&PathBuf> {
+        self.mirror_dir.as_ref()
+    }
+    pub fn table_format(&self) -> TableFormat {
+        self.table_format
+    }
+    pub fn sqlite_path(&self) -> Option<&PathBuf> {
+        self.sqlite_path.as_ref()
+    }
+    pub fn parquet_path(&self) -> Option<&PathBuf> {
+        self.parquet_path.as_ref()
+    }
+    pub fn parquet_compression(&self) -> ParquetCompression {
+        self.parquet_compression
+    }
+    pub fn parquet_row_group_pages(&self) -> usize {
+        self.parquet_row_group_pages
+    }
+    pub fn jobs(&self) -> Option<usize> {
+        self.jobs
+    }
+    pub fn queue_size(&self) -> usize {
+        self.queue_size
+    }
+    pub fn resume(&self) -> bool {
+        self.resume
+    }
+    pub fn dedupe(&self) -> bool {
+        self.dedupe
+    }
+    pub fn dedupe_hashes(&self) -> Option<&PathBuf> {
+        self.dedupe_hashes.as_ref()
+    }
+    pub fn duplicates_log(&self) -> Option<&PathBuf> {
+        self.duplicates_log.as_ref()
+    }
+    pub fn error_log_format(&self) -> ErrorLogFormat {
+        self.error_log_format
+    }
+    pub fn ordered(&self) -> bool {
+        self.ordered
+    }
+    pub fn watch(&self) -> bool {
+        self.watch
+    }
+    pub fn progress(&self) -> bool {
+        self.progress
+    }
+    pub fn stats_json(&self) -> Option<&PathBuf> {
+        self.stats_json.as_ref()
+    }
     pub fn path_file(&self) -> Option<&PathBuf> {
         self.path_file.as_ref()
     }
@@ -175,12 +497,85 @@ impl<'a> From<clap::ArgMatches<'a>> for Configuration {
         Self {
             paths,
             use_stdin: (!matches.is_present("path") && is_readable_stdin() || found_stdin_path),
-            compact_output: (matches.is_present("compact output") || !is_tty_stdout()),
+            // mirrored files are meant to be read, they're only compact when asked for
+            compact_output: (matches.is_present("compact output")
+                || matches.is_present("output dir")
+                || (!is_tty_stdout() && matches.value_of("output format") != Some("mirror"))),
             fail_path: matches.value_of("fail file").map(|v| PathBuf::from(v)),
-            success_path: matches.value_of("success file").map(|v| PathBuf::from(v)),
+            error_log_format: matches
+                .value_of("error log format")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default(),
+            success_path: matches
+                .value_of_os("success file")
+                .or_else(|| matches.value_of_os("resume"))
+                .map(PathBuf::from),
+            resume: matches.is_present("resume"),
+            dedupe: matches.is_present("dedupe"),
+            dedupe_hashes: matches.value_of_os("dedupe hashes").map(PathBuf::from),
+            duplicates_log: matches.value_of_os("duplicates log").map(PathBuf::from),
             recursive: matches.is_present("recursive"),
             path_file: matches.value_of("path file").map(PathBuf::from),
+            ordered: matches.is_present("ordered"),
+            watch: matches.is_present("watch"),
+            progress: !matches.is_present("no progress") && is_tty_stderr(),
+            stats_json: matches.value_of_os("stats json").map(PathBuf::from),
             source_label: matches.value_of("source label").map(String::from),
+            warc: matches.is_present("warc"),
+            reference_time: matches
+                .value_of("reference time")
+                .and_then(|v| parse_reference_time(v).ok()),
+            lenient: matches.is_present("lenient"),
+            required_fields: if matches.is_present("strict") {
+                Some(
+                    matches
+                        .values_of("required fields")
+                        .map(|v| v.filter_map(|v| v.parse().ok()).collect())
+                        .unwrap_or_else(|| RequiredField::ALL.to_vec()),
+                )
+            } else {
+                None
+            },
+            output_format: matches
+                .value_of("output format")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default(),
+            output_dir: matches.value_of_os("output dir").map(PathBuf::from),
+            shard_limits: ShardLimits {
+                documents: matches
+                    .value_of("shard documents")
+                    .and_then(|v| v.parse().ok()),
+                bytes: matches
+                    .value_of("shard bytes")
+                    .and_then(|v| parse_size(v).ok()),
+            },
+            shard_compression: matches
+                .value_of("shard compression")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default(),
+            es_index: matches.value_of("es index").unwrap_or("parler").into(),
+            es_split_posts: matches.is_present("es split posts"),
+            tables_dir: matches.value_of_os("tables dir").map(PathBuf::from),
+            mirror_dir: matches.value_of_os("mirror dir").map(PathBuf::from),
+            table_format: matches
+                .value_of("table format")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default(),
+            sqlite_path: matches.value_of_os("sqlite").map(PathBuf::from),
+            parquet_path: matches.value_of_os("parquet").map(PathBuf::from),
+            parquet_compression: matches
+                .value_of("parquet compression")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default(),
+            parquet_row_group_pages: matches
+                .value_of("parquet row group pages")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1000),
+            jobs: matches.value_of("jobs").and_then(|v| v.parse().ok()),
+            queue_size: matches
+                .value_of("queue size")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1000),
         }
     }
 }
@@ -221,4 +616,317 @@ mod tests {
         assert_eq!(config.compact_output, true);
         Ok(())
     }
+    #[test]
+    fn test_arg_parser_output_format() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec!["test"])?);
+        assert_eq!(config.output_format, OutputFormat::Json);
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--output-format",
+            "es-bulk",
+            "--es-index",
+            "posts",
+            "--es-split-posts",
+        ])?);
+        assert_eq!(config.output_format, OutputFormat::EsBulk);
+        assert_eq!(config.es_index, "posts");
+        assert_eq!(config.es_split_posts, true);
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--output-format", "xml"])
+            .is_err());
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--output-format",
+            "merged-posts",
+        ])?);
+        assert_eq!(config.output_format, OutputFormat::MergedPosts);
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--output-format",
+            "mirror",
+            "--mirror-dir",
+            "out",
+        ])?);
+        assert_eq!(config.output_format, OutputFormat::Mirror);
+        assert_eq!(config.mirror_dir, Some(PathBuf::from("out")));
+        assert_eq!(config.compact_output, false);
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--output-format", "mirror"])
+            .is_err());
+        // normalized output needs somewhere to put the tables
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--output-format", "normalized"])
+            .is_err());
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--output-format",
+            "normalized",
+            "--tables-dir",
+            "/tmp/tables",
+            "--table-format",
+            "ndjson",
+        ])?);
+        assert_eq!(config.tables_dir, Some(PathBuf::from("/tmp/tables")));
+        assert_eq!(config.table_format, TableFormat::Ndjson);
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_resume() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--resume",
+            "done.log",
+        ])?);
+        assert_eq!(config.resume, true);
+        assert_eq!(config.success_path, Some(PathBuf::from("done.log")));
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--success-log",
+            "done.log",
+        ])?);
+        assert_eq!(config.resume, false);
+        // resuming already appends to the log it reads
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--resume", "a.log", "--success-log", "b.log"])
+            .is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_dedupe() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec!["test"])?);
+        assert_eq!(config.dedupe, false);
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--dedupe",
+            "--dedupe-hashes",
+            "hashes.tsv",
+            "--duplicates-log",
+            "dupes.tsv",
+        ])?);
+        assert_eq!(config.dedupe, true);
+        assert_eq!(config.dedupe_hashes, Some(PathBuf::from("hashes.tsv")));
+        assert_eq!(config.duplicates_log, Some(PathBuf::from("dupes.tsv")));
+        for arg in &["--dedupe-hashes", "--duplicates-log"] {
+            assert!(parse_args()
+                .get_matches_from_safe(vec!["test", arg, "file.tsv"])
+                .is_err());
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_error_log_format() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--fail-log",
+            "failed.log",
+        ])?);
+        assert_eq!(config.error_log_format, ErrorLogFormat::Plain);
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--fail-log",
+            "failed.ndjson",
+            "--error-log-format",
+            "json",
+        ])?);
+        assert_eq!(config.error_log_format, ErrorLogFormat::Json);
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--error-log-format", "json"])
+            .is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_sqlite() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec!["test"])?);
+        assert_eq!(config.sqlite_path, None);
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--sqlite",
+            "corpus.db",
+        ])?);
+        assert_eq!(config.sqlite_path, Some(PathBuf::from("corpus.db")));
+        assert_eq!(config.output_format, OutputFormat::Json);
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_parquet() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec!["test"])?);
+        assert_eq!(config.parquet_path, None);
+        assert_eq!(config.parquet_compression, ParquetCompression::Snappy);
+        assert_eq!(config.parquet_row_group_pages, 1000);
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--parquet",
+            "posts.parquet",
+            "--parquet-compression",
+            "zstd",
+            "--parquet-row-group-pages",
+            "50",
+        ])?);
+        assert_eq!(config.parquet_path, Some(PathBuf::from("posts.parquet")));
+        assert_eq!(config.parquet_compression, ParquetCompression::Zstd);
+        assert_eq!(config.parquet_row_group_pages, 50);
+        for pages in &["0", "-1", "many"] {
+            assert!(parse_args()
+                .get_matches_from_safe(vec!["test", "--parquet-row-group-pages", pages])
+                .is_err());
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_lenient() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec!["test"])?);
+        assert_eq!(config.lenient, false);
+        let config =
+            Configuration::from(parse_args().get_matches_from_safe(vec!["test", "--lenient"])?);
+        assert_eq!(config.lenient, true);
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_strict() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec!["test"])?);
+        assert_eq!(config.required_fields, None);
+        let config =
+            Configuration::from(parse_args().get_matches_from_safe(vec!["test", "--strict"])?);
+        assert_eq!(config.required_fields, Some(RequiredField::ALL.to_vec()));
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--strict",
+            "--required-fields",
+            "post-id,timestamp",
+        ])?);
+        assert_eq!(
+            config.required_fields,
+            Some(vec![RequiredField::PostId, RequiredField::Timestamp])
+        );
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--strict", "--required-fields", "likes"])
+            .is_err());
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--required-fields", "post-id"])
+            .is_err());
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--strict", "--lenient"])
+            .is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_progress() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec!["test"])?);
+        assert_eq!(config.progress, is_tty_stderr());
+        assert_eq!(config.stats_json, None);
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--no-progress",
+            "--stats-json",
+            "stats.json",
+        ])?);
+        assert_eq!(config.progress, false);
+        assert_eq!(config.stats_json, Some(PathBuf::from("stats.json")));
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_jobs() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec!["test"])?);
+        assert_eq!(config.jobs, None);
+        assert_eq!(config.queue_size, 1000);
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "-j",
+            "4",
+            "--queue-size",
+            "64",
+        ])?);
+        assert_eq!(config.jobs, Some(4));
+        assert_eq!(config.queue_size, 64);
+        for arg in &["--jobs", "--queue-size"] {
+            assert!(parse_args()
+                .get_matches_from_safe(vec!["test", arg, "0"])
+                .is_err());
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_ordered() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec!["test"])?);
+        assert_eq!(config.ordered, false);
+        let config =
+            Configuration::from(parse_args().get_matches_from_safe(vec!["test", "--ordered"])?);
+        assert_eq!(config.ordered, true);
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_watch() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec!["test"])?);
+        assert_eq!(config.watch, false);
+        let config = Configuration::from(
+            parse_args().get_matches_from_safe(vec!["test", "-r", "--watch", "spool"])?,
+        );
+        assert_eq!(config.watch, true);
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--watch", "spool"])
+            .is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_output_dir() -> clap::Result<()> {
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec!["test"])?);
+        assert_eq!(config.output_dir, None);
+        assert_eq!(config.shard_limits, ShardLimits::default());
+        let config = Configuration::from(parse_args().get_matches_from_safe(vec![
+            "test",
+            "--output-dir",
+            "out",
+            "--shard-documents",
+            "100000",
+            "--shard-bytes",
+            "1G",
+            "--shard-compression",
+            "zstd",
+        ])?);
+        assert_eq!(config.output_dir, Some(PathBuf::from("out")));
+        assert_eq!(config.compact_output, true);
+        assert_eq!(config.shard_limits.documents, Some(100000));
+        assert_eq!(config.shard_limits.bytes, Some(1 << 30));
+        assert_eq!(config.shard_compression, ShardCompression::Zstd);
+        for args in &[["--shard-documents", "10"], ["--shard-compression", "gzip"]] {
+            assert!(parse_args()
+                .get_matches_from_safe(vec!["test", args[0], args[1]])
+                .is_err());
+        }
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--output-dir", "out", "--shard-bytes", "lots"])
+            .is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_schema_subcommand() -> clap::Result<()> {
+        let matches = parse_args().get_matches_from_safe(vec!["test", "schema"])?;
+        assert!(matches.subcommand_matches("schema").is_some());
+        assert!(!matches.is_present("path"));
+        Ok(())
+    }
+    #[test]
+    fn test_arg_parser_reference_time() -> clap::Result<()> {
+        let app = parse_args();
+        let config = Configuration::from(app.get_matches_from_safe(vec![
+            "test",
+            "--reference-time",
+            "2021-01-10T00:00:00Z",
+        ])?);
+        assert_eq!(config.reference_time, Some(1610236800));
+        let app = parse_args();
+        let config = Configuration::from(app.get_matches_from_safe(vec![
+            "test",
+            "--reference-time",
+            "1610236800",
+        ])?);
+        assert_eq!(config.reference_time, Some(1610236800));
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--reference-time", "yesterday"])
+            .is_err());
+        assert!(parse_args()
+            .get_matches_from_safe(vec!["test", "--reference-time", "1969-07-20T20:17:00Z"])
+            .is_err());
+        Ok(())
+    }
 }
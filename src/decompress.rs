@@ -0,0 +1,94 @@
+use std::io::{self, BufRead, BufReader};
+
+// Sniff the first few bytes of the input and transparently decompress it.
+// Extensions lie (or are missing on stdin), magic bytes don't.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if header.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+            Compression::Bzip2 => "bz2",
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "bz2" => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+}
+
+pub fn decompress<'a>(mut reader: Box<dyn BufRead + 'a>) -> io::Result<Box<dyn BufRead + 'a>> {
+    // fill_buf may return less than the magic length on tiny/short reads,
+    // that's fine, those inputs just won't be detected as compressed
+    let kind = Compression::detect(reader.fill_buf()?);
+    Ok(match kind {
+        // multi-member decoders since concatenated members are common (.warc.gz)
+        Some(Compression::Gzip) => Box::new(BufReader::new(
+            flate2::bufread::MultiGzDecoder::new(reader),
+        )),
+        Some(Compression::Zstd) => Box::new(BufReader::new(
+            zstd::stream::read::Decoder::with_buffer(reader)?,
+        )),
+        Some(Compression::Bzip2) => Box::new(BufReader::new(
+            bzip2::bufread::MultiBzDecoder::new(reader),
+        )),
+        None => reader,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    fn roundtrip(compressed: Vec<u8>) -> String {
+        let mut out = String::new();
+        decompress(Box::new(io::Cursor::new(compressed)))
+            .unwrap()
+            .read_to_string(&mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn detects_by_magic() {
+        let html = "<html><body>hello</body></html>";
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(html.as_bytes()).unwrap();
+        assert_eq!(roundtrip(gz.finish().unwrap()), html);
+
+        let zst = zstd::stream::encode_all(html.as_bytes(), 0).unwrap();
+        assert_eq!(roundtrip(zst), html);
+
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz.write_all(html.as_bytes()).unwrap();
+        assert_eq!(roundtrip(bz.finish().unwrap()), html);
+
+        assert_eq!(roundtrip(html.as_bytes().to_vec()), html);
+    }
+}
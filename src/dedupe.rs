@@ -0,0 +1,111 @@
+use crate::resume::{is_sha1, open_append, read_lines};
+use anyhow::{Context, Result};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::File,
+    io::{BufWriter, Read, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+// WARC records all share the path of the WARC file, the record id tells them apart
+pub fn document_name(path: &Path, warc_record_id: Option<&str>) -> String {
+    let path = grep_cli::escape_os(path.as_os_str());
+    match warc_record_id {
+        Some(id) => format!("{}#{}", path, id),
+        None => path,
+    }
+}
+
+/// First document seen for every sha1, shared by all the parsing threads
+#[derive(Debug, Default)]
+pub struct SeenHashes {
+    seen: Mutex<HashMap<String, String>>,
+}
+
+impl SeenHashes {
+    /// Reads a hash log written by [`HashLog`], lines are `<sha1>\t<document>`
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        let mut seen = HashMap::new();
+        read_lines(reader, |line| {
+            if let Some((sha1, name)) = line.split_once('\t') {
+                if is_sha1(sha1) {
+                    seen.entry(sha1.to_string()).or_insert_with(|| name.to_string());
+                }
+            }
+        })?;
+        Ok(Self {
+            seen: Mutex::new(seen),
+        })
+    }
+
+    /// Reads the hash log at `path`, a missing log is the same as an empty one
+    pub fn open(path: &Path) -> Result<Self> {
+        match File::open(path) {
+            Ok(file) => Self::read(file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+        .with_context(|| format!("failed to read hash log {}", path.display()))
+    }
+
+    /// Records `name` as the first document with this hash, or returns the one that was first
+    pub fn first_seen(&self, sha1: &str, name: String) -> Option<String> {
+        let mut seen = self.seen.lock().unwrap();
+        match seen.entry(sha1.to_string()) {
+            Entry::Occupied(e) => Some(e.get().clone()),
+            Entry::Vacant(e) => {
+                e.insert(name);
+                None
+            }
+        }
+    }
+}
+
+/// Hashes of emitted documents, read back with [`SeenHashes::open`] on the next run
+pub struct HashLog {
+    out: BufWriter<File>,
+}
+
+impl HashLog {
+    pub fn append(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(open_append(path)?),
+        })
+    }
+
+    pub fn push(&mut self, sha1: &str, name: &str) -> std::io::Result<()> {
+        writeln!(self.out, "{}\t{}", sha1, name)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA1: &str = "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12";
+
+    #[test]
+    fn keeps_the_first_document() -> Result<()> {
+        let log = format!("{}\told/a.html\n{}\told/b.html\nnot a hash\tc.html\n", SHA1, SHA1);
+        let seen = SeenHashes::read(log.as_bytes())?;
+        assert_eq!(seen.first_seen(SHA1, "new.html".into()), Some("old/a.html".into()));
+        let other = "de9f2c7fd25e1b3afad3e85a0bd17d9b100db4b3";
+        assert_eq!(seen.first_seen(other, "x.html".into()), None);
+        assert_eq!(seen.first_seen(other, "y.html".into()), Some("x.html".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn names_warc_records() {
+        assert_eq!(document_name(Path::new("a/b.html"), None), "a/b.html");
+        assert_eq!(
+            document_name(Path::new("crawl.warc.gz"), Some("<urn:uuid:1234>")),
+            "crawl.warc.gz#<urn:uuid:1234>"
+        );
+    }
+}
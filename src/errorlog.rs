@@ -0,0 +1,231 @@
+use crate::validate::Violation;
+use crate::ProcessingError;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::{error::Error, io::Write, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ErrorLogFormat {
+    /// One escaped path per line
+    #[default]
+    Plain,
+    /// One [`ErrorRecord`] per line
+    Json,
+}
+
+impl ErrorLogFormat {
+    pub const NAMES: &'static [&'static str] = &["plain", "json"];
+}
+
+impl FromStr for ErrorLogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(ErrorLogFormat::Plain),
+            "json" => Ok(ErrorLogFormat::Json),
+            _ => Err(anyhow!("unknown error log format {}", s)),
+        }
+    }
+}
+
+/// What unhtml could tell us about a failed extraction. It doesn't know the selector, only the
+/// type that was being parsed when a text conversion failed.
+#[derive(Debug, Serialize)]
+pub struct ParseFailure {
+    /// `SourceNotFound` when a required element was missing, `AttrNotFound` when an element
+    /// lacked a required attribute, `TextParseError` when it was there but couldn't be converted
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    type_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    /// The missing attribute
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attr: Option<String>,
+    /// The html of the element that lacked it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    src: Option<String>,
+}
+
+impl From<&unhtml::Error> for ParseFailure {
+    fn from(e: &unhtml::Error) -> Self {
+        let failure = |kind| Self {
+            kind,
+            type_name: None,
+            text: None,
+            reason: None,
+            attr: None,
+            src: None,
+        };
+        match e {
+            unhtml::Error::SourceNotFound => failure("SourceNotFound"),
+            unhtml::Error::AttrNotFound { attr, src } => Self {
+                attr: Some(attr.clone()),
+                src: Some(src.clone()),
+                ..failure("AttrNotFound")
+            },
+            unhtml::Error::TextParseError {
+                text,
+                type_name,
+                err,
+            } => Self {
+                type_name: Some(type_name.clone()),
+                text: Some(text.clone()),
+                reason: Some(err.clone()),
+                ..failure("TextParseError")
+            },
+        }
+    }
+}
+
+/// A failed input, one line of the json error log
+#[derive(Debug, Serialize)]
+pub struct ErrorRecord {
+    /// Escaped like the paths in the output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The `ProcessingError` variant
+    pub kind: &'static str,
+    pub message: String,
+    /// Every error in the `source()` chain, outermost first
+    pub causes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_failure: Option<ParseFailure>,
+    /// The `--strict` rules the page failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_rules: Option<Vec<Violation>>,
+}
+
+impl From<&ProcessingError> for ErrorRecord {
+    fn from(e: &ProcessingError) -> Self {
+        let mut causes = Vec::new();
+        let mut source = e.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        let (sha1, parse_failure, failed_rules) = match e {
+            ProcessingError::ParlerParseError { sha1, source, .. } => {
+                (Some(sha1.clone()), Some(ParseFailure::from(source)), None)
+            }
+            ProcessingError::ValidationError {
+                sha1, violations, ..
+            } => (Some(sha1.clone()), None, Some(violations.0.clone())),
+            _ => (None, None, None),
+        };
+        Self {
+            path: e.path().map(|v| grep_cli::escape_os(v.as_os_str())),
+            kind: e.kind(),
+            message: e.to_string(),
+            causes,
+            sha1,
+            parse_failure,
+            failed_rules,
+        }
+    }
+}
+
+impl ErrorRecord {
+    pub fn write<W: Write>(&self, out: &mut W, format: ErrorLogFormat) -> Result<()> {
+        match format {
+            // the plain log has always been a list of paths, errors without one are left out
+            ErrorLogFormat::Plain => {
+                if let Some(path) = &self.path {
+                    writeln!(out, "{}", path)?;
+                }
+            }
+            ErrorLogFormat::Json => {
+                serde_json::to_writer(&mut *out, self)?;
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io, path::PathBuf};
+
+    #[test]
+    fn records_the_source_chain() -> Result<()> {
+        let e = ProcessingError::FileIO {
+            path: PathBuf::from("a\tb.html"),
+            source: anyhow::Error::from(io::Error::new(io::ErrorKind::NotFound, "gone"))
+                .context("failed to open"),
+        };
+        let record = ErrorRecord::from(&e);
+        assert_eq!(record.kind, "FileIO");
+        assert_eq!(record.path.as_deref(), Some("a\\tb.html"));
+        assert_eq!(record.causes, vec!["failed to open", "gone"]);
+
+        let mut plain = Vec::new();
+        record.write(&mut plain, ErrorLogFormat::Plain)?;
+        assert_eq!(plain, b"a\\tb.html\n");
+        Ok(())
+    }
+
+    #[test]
+    fn records_parse_failures() -> Result<()> {
+        let e = ProcessingError::ParlerParseError {
+            path: PathBuf::from("post.html"),
+            sha1: "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12".into(),
+            source: unhtml::Error::TextParseError {
+                text: "/badges/99.png".into(),
+                type_name: "Badge".into(),
+                err: "invalid badge url".into(),
+            },
+        };
+        let mut json = Vec::new();
+        ErrorRecord::from(&e).write(&mut json, ErrorLogFormat::Json)?;
+        let json: serde_json::Value = serde_json::from_slice(&json)?;
+        assert_eq!(json["kind"], "ParlerParseError");
+        assert_eq!(json["sha1"], "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12");
+        assert_eq!(json["parse_failure"]["kind"], "TextParseError");
+        assert_eq!(json["parse_failure"]["type_name"], "Badge");
+
+        let failure = serde_json::to_value(ParseFailure::from(&unhtml::Error::AttrNotFound {
+            attr: "href".into(),
+            src: "<a class=\"at\">@someone</a>".into(),
+        }))?;
+        assert_eq!(
+            failure,
+            serde_json::json!({
+                "kind": "AttrNotFound",
+                "attr": "href",
+                "src": "<a class=\"at\">@someone</a>",
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn records_failed_rules() -> Result<()> {
+        use crate::validate::{RequiredField, Violations};
+        let e = ProcessingError::ValidationError {
+            path: PathBuf::from("post.html"),
+            sha1: "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12".into(),
+            violations: Violations(vec![Violation {
+                rule: RequiredField::BodyOrMedia,
+                field: "posts[0].cards[1]".into(),
+            }]),
+        };
+        let mut json = Vec::new();
+        ErrorRecord::from(&e).write(&mut json, ErrorLogFormat::Json)?;
+        let json: serde_json::Value = serde_json::from_slice(&json)?;
+        assert_eq!(json["kind"], "ValidationError");
+        assert_eq!(
+            json["message"],
+            "post.html failed validation: body-or-media at posts[0].cards[1]"
+        );
+        assert_eq!(json["failed_rules"][0]["rule"], "body-or-media");
+        assert_eq!(json["failed_rules"][0]["field"], "posts[0].cards[1]");
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+//! Parses archived Parler HTML pages into structured data.
+//!
+//! ```no_run
+//! let html = std::fs::read_to_string("post.html").unwrap();
+//! let page = parler_indexer::parse_page_str(&html).unwrap();
+//! println!("{} posts", page.posts.len());
+//! ```
+
+pub mod archive;
+pub mod decompress;
+pub mod parse;
+pub mod warc;
+
+use html5ever::{tendril::TendrilSink, ParseOpts};
+use sha1::{Digest, Sha1};
+use std::io::{self, BufReader, Read};
+use tee::TeeReader;
+use thiserror::Error;
+
+pub use parse::media::{MediaItem, MediaKind, ResourceLink};
+pub use parse::meta::{ParseOutput, ScrapeMeta};
+pub use parse::opengraph::OGMeta;
+pub use parse::page::{ParlerPage, ParseWarning};
+pub use parse::post::{Comment, ParlerPost, PostCard};
+pub use parse::profile::{Author, Badge, ParlerProfile};
+pub use unhtml::scraper::Html;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("failed to read document: {0}")]
+    Io(#[from] io::Error),
+    #[error("found no match: {0}")]
+    Parse(#[from] unhtml::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Parses an html document into a [`ParlerPage`].
+pub fn parse_page_str(html: &str) -> Result<ParlerPage> {
+    parse_page_html(&Html::parse_document(html))
+}
+
+/// Reads and parses an html document. Compressed input (gzip, zstd, bzip2) is decompressed first.
+pub fn parse_page_reader<R: Read>(reader: R) -> Result<ParlerPage> {
+    let mut reader = decompress::decompress(Box::new(BufReader::new(reader)))?;
+    let (_, doc) = read_document(&mut reader)?;
+    parse_page_html(&doc)
+}
+
+/// Extracts a [`ParlerPage`] from an already parsed document.
+pub fn parse_page_html(doc: &Html) -> Result<ParlerPage> {
+    use unhtml::Element;
+    let sel = unhtml::scraper::Selector::parse(":root").unwrap();
+    Ok(doc.select(&sel).element()?)
+}
+
+/// Like [`parse_page_html`], but the parts that fail to parse are left out and listed in
+/// [`ParlerPage::warnings`] instead of failing the whole page.
+pub fn parse_page_html_lenient(doc: &Html) -> Result<ParlerPage> {
+    let sel = unhtml::scraper::Selector::parse(":root").unwrap();
+    Ok(ParlerPage::from_elements_lenient(&mut doc.select(&sel))?)
+}
+
+/// JSON Schema (draft 2020-12) of the documents written by the CLI
+pub fn output_schema() -> schemars::Schema {
+    schemars::schema_for!(ParseOutput)
+}
+
+/// Parses an html document and returns it along with the hex encoded sha1 of its bytes.
+pub fn read_document<R: Read>(source: &mut R) -> io::Result<(String, Html)> {
+    let doc = Html::new_document();
+    let parser = html5ever::parse_document(doc, ParseOpts::default());
+    let mut hasher = Sha1::new();
+    let res = {
+        let mut tee = TeeReader::new(source, &mut hasher);
+        parser.from_utf8().read_from(&mut tee)?
+    };
+    Ok((format!("{:x}", hasher.finalize()), res))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/examples/echo--parent-no-comment.html"
+    );
+
+    #[test]
+    fn parses_str_and_reader() {
+        let html = std::fs::read_to_string(EXAMPLE).unwrap();
+        let from_str = parse_page_str(&html).unwrap();
+        assert!(!from_str.posts.is_empty());
+        let from_reader = parse_page_reader(std::fs::File::open(EXAMPLE).unwrap()).unwrap();
+        assert_eq!(from_str, from_reader);
+    }
+
+    // Objects in the generated schema allow any extra property, so disallow them here to
+    // catch fields that are serialized but missing from the schema as well. Flattened fields
+    // come in through a `$ref` next to the properties, `unevaluatedProperties` counts those
+    // as long as the flattened definition itself stays open.
+    fn close_objects(schema: &mut serde_json::Value, flattened: &[String]) {
+        match schema {
+            serde_json::Value::Object(map) => {
+                if map.contains_key("properties") && !map.contains_key("additionalProperties") {
+                    map.insert("unevaluatedProperties".into(), false.into());
+                }
+                for (key, value) in map.iter_mut() {
+                    if key != "$defs" {
+                        close_objects(value, flattened);
+                    }
+                }
+                if let Some(serde_json::Value::Object(defs)) = map.get_mut("$defs") {
+                    for (name, def) in defs.iter_mut() {
+                        if !flattened.contains(name) {
+                            close_objects(def, flattened);
+                        }
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                items.iter_mut().for_each(|v| close_objects(v, flattened))
+            }
+            _ => {}
+        }
+    }
+
+    fn flattened_defs(schema: &serde_json::Value, found: &mut Vec<String>) {
+        match schema {
+            serde_json::Value::Object(map) => {
+                if let (Some(serde_json::Value::String(target)), true) =
+                    (map.get("$ref"), map.contains_key("properties"))
+                {
+                    found.extend(target.strip_prefix("#/$defs/").map(String::from));
+                }
+                map.values().for_each(|v| flattened_defs(v, found));
+            }
+            serde_json::Value::Array(items) => items.iter().for_each(|v| flattened_defs(v, found)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn output_matches_schema() {
+        use parse::meta::{InputKind, OutputBuilder, WarcMeta};
+
+        let (sha1, doc) = read_document(&mut std::fs::File::open(EXAMPLE).unwrap()).unwrap();
+        let mut builder = OutputBuilder::new(InputKind::HTML, EXAMPLE.into());
+        builder
+            .sha1(sha1)
+            .reference_dt(Some(1610280000))
+            .warc(Some(WarcMeta {
+                target_uri: Some("https://parler.com/post/abc".into()),
+                date: Some("2021-01-10T12:00:00Z".into()),
+                record_id: Some("<urn:uuid:1234>".into()),
+            }));
+        let output = builder.build(parse_page_html(&doc).unwrap()).unwrap();
+
+        let mut schema = serde_json::to_value(output_schema()).unwrap();
+        let mut flattened = Vec::new();
+        flattened_defs(&schema, &mut flattened);
+        close_objects(&mut schema, &flattened);
+        let validator = jsonschema::validator_for(&schema).unwrap();
+        let instance = serde_json::to_value(&output).unwrap();
+        let errors: Vec<_> = validator
+            .iter_errors(&instance)
+            .map(|e| format!("{} at {}", e, e.instance_path))
+            .collect();
+        assert!(errors.is_empty(), "{:#?}", errors);
+    }
+
+    #[test]
+    fn lenient_matches_strict() {
+        let examples = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/examples")).unwrap();
+        for path in examples.map(|v| v.unwrap().path()) {
+            if path.extension().is_none_or(|v| v != "html") {
+                continue;
+            }
+            let doc = Html::parse_document(&std::fs::read_to_string(&path).unwrap());
+            let lenient = parse_page_html_lenient(&doc).unwrap();
+            assert!(lenient.warnings.is_empty(), "{}", path.display());
+            assert_eq!(lenient, parse_page_html(&doc).unwrap(), "{}", path.display());
+        }
+    }
+
+    // Timestamps and comments don't fail on text they can't make sense of, those fields are
+    // left empty and both parses agree
+    #[test]
+    fn lenient_matches_strict_on_odd_text() {
+        let comment = |ts: &str, body: &str| {
+            format!(
+                r#"<div class="comment--card--wrapper"><div class="card--comment-container">
+                <div class="card--header"><span class="card-meta--row"><span class="post--timestamp">{}</span></span></div>
+                <div class="card--body"><p>{}</p></div></div></div>"#,
+                ts, body
+            )
+        };
+        let comments = format!(
+            r#"<div class="comments-list--container" id="comments--b9a68d4725a347b3950e13c591942c79">{}{}</div>"#,
+            comment("2 hours ago", "fine"),
+            comment("at some point", "odd"),
+        );
+        let html = std::fs::read_to_string(EXAMPLE)
+            .unwrap()
+            .replace(
+                r#"<span class="post--timestamp">3 days ago</span>"#,
+                r#"<span class="post--timestamp">a while ago</span>"#,
+            )
+            .replace(r#"<div class="card--footer">"#, &format!(r#"{}<div class="card--footer">"#, comments));
+        let doc = Html::parse_document(&html);
+        let page = parse_page_html_lenient(&doc).unwrap();
+        assert!(page.warnings.is_empty());
+        assert_eq!(page, parse_page_html(&doc).unwrap());
+
+        let post = &page.posts[0];
+        assert_eq!(post.cards[0].rel_timestamp.rel_ts.as_deref(), Some("a while ago"));
+        assert_eq!(post.cards[0].rel_timestamp.approx_ts_offset, None);
+        assert_eq!(post.comments.len(), 2);
+        assert_eq!(post.comments[1].rel_timestamp.approx_ts_offset, None);
+        assert_eq!(post.post_id.as_ref().unwrap().as_ref(), "b9a68d4725a347b3950e13c591942c79");
+    }
+
+    // Broken og tags fail the regular parse, the lenient one keeps the posts. The card that
+    // lost its timestamp block is kept without one.
+    #[test]
+    fn lenient_keeps_page_with_broken_og_tags() {
+        let html = std::fs::read_to_string(EXAMPLE)
+            .unwrap()
+            .replace(r#"<meta property="og:url""#, r#"<meta property="og:broken""#)
+            .replace(r#"<span class="post--timestamp">3 days ago</span>"#, "");
+        let doc = Html::parse_document(&html);
+        assert!(parse_page_html(&doc).is_err());
+
+        let page = parse_page_html_lenient(&doc).unwrap();
+        assert_eq!(
+            serde_json::to_value(&page.warnings).unwrap(),
+            serde_json::json!([{"field": "opengraph_meta", "error": "source not found"}])
+        );
+        assert_eq!(page.opengraph_meta, None);
+        assert_eq!(page.posts.len(), 1);
+        let card = &page.posts[0].cards[0];
+        assert_eq!(card.kind, parse::post::PostCardType::EchoParent);
+        assert_eq!(card.rel_timestamp.rel_ts, None);
+    }
+}
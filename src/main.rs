@@ -1,86 +1,180 @@
-mod parse;
 use anyhow::*;
 use args::{Configuration};
-use html5ever::{ParseOpts, parse_document, tendril::TendrilSink, tokenizer::TokenizerOpts, tree_builder::TreeBuilderOpts};
 use io::{BufRead, BufWriter, Stdin, Stdout};
-use parse::page::ParlerPage;
-use parse::parser::*;
+use parler_indexer::{archive, decompress, read_document, warc};
+use parler_indexer::parse::meta::*;
+use dedupe::{document_name, HashLog, SeenHashes};
+use errorlog::ErrorRecord;
+use progress::{Progress, Stats};
+use reorder::SharedReorderBuffer;
+use resume::{open_append, ResumeLog, SuccessLog};
+use shutdown::Shutdown;
+use validate::{validate, Violations};
+use watch::Watcher;
+use output::{is_up_to_date, mirror_path, DocumentWrite, EsBulkSink, JsonSink, MergeSink, MirrorSink, OutputFormat, ParquetSink, RelationalSink, ShardWriter, Sink, SqliteSink};
 use serde_json::{self, to_writer};
 use std::{borrow::{Borrow, BorrowMut}, fs::{File, read}, io::{self, BufReader, Read, StdoutLock}, path::{Path, PathBuf}};
 use std::{cell::RefCell, fmt};
 use unhtml::{scraper::html, Element};
-use walkdir::WalkDir;
+use walkdir::{DirEntry, WalkDir};
 use ProcessingError::FileIO;
 mod args;
-use tee::*;
+mod dedupe;
+mod errorlog;
+mod output;
+mod progress;
+mod reorder;
+mod resume;
+mod shutdown;
+mod validate;
+mod watch;
 use anyhow::Result;
 use crossbeam_channel::{bounded, SendError};
 use rayon::{prelude::*, spawn};
 use std::io::Write;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use thiserror::Error;
-use sha1::*;
-use parse::meta::*;
 
 #[derive(Debug)]
 enum InputStream {
     File(std::fs::File),
     Path(PathBuf),
-    
+    Bytes(Vec<u8>),
     Stdin,
 }
 
 
-
-
-
-
-fn read_buf_document<T>(source: &mut T) -> anyhow::Result<(String, unhtml::scraper::Html)>
-where
-    T: Read,
-{
-    let mut reader = io::BufReader::new(source);
-    read_document(&mut reader)
-}
-fn read_document<T>(source: &mut T) -> anyhow::Result<(String, unhtml::scraper::Html)>
-where
-    T: Read,
-{
-    let doc = unhtml::scraper::Html::new_document();
-    let parser = html5ever::parse_document(doc, ParseOpts::default());
-    let mut hasher = Sha1::new();
-    let res = {
-        let mut tee = TeeReader::new(source, &mut hasher);
-        parser.from_utf8().read_from(&mut tee)?
-    };
-    Ok((std::format!("{:x}", hasher.finalize()), res))
-}
-
 impl InputStream {
-    fn read_document(&mut self) -> anyhow::Result<(String, unhtml::scraper::Html)> {
-        match self {
-            InputStream::File(f) => read_buf_document(f),
-            InputStream::Stdin => {
-                let stdin = std::io::stdin();
-                let mut lock = stdin.lock();
-                read_document(&mut lock)
-            }
+    fn into_reader(self) -> anyhow::Result<Box<dyn BufRead>> {
+        let reader: Box<dyn BufRead> = match self {
+            InputStream::File(f) => Box::new(BufReader::new(f)),
+            InputStream::Stdin => Box::new(BufReader::new(std::io::stdin())),
+            InputStream::Bytes(b) => Box::new(io::Cursor::new(b)),
             InputStream::Path(p) => {
-                let mut file = std::fs::File::open(p.as_path().borrow()).map_err(|e| {
+                let file = std::fs::File::open(p.as_path()).map_err(|e| {
                     ProcessingError::FileIO {
                         path: p.as_path().to_path_buf(),
                         source: e.into(),
                     }
                 })?;
-                read_buf_document(&mut file)
+                Box::new(BufReader::new(file))
+            }
+        };
+        decompress::decompress(reader).context("failed to read compressed input")
+    }
+    fn read_document(self) -> anyhow::Result<(String, parler_indexer::Html)> {
+        let mut reader = self.into_reader()?;
+        Ok(read_document(&mut reader)?)
+    }
+}
+
+type Input = Result<(OutputBuilder, InputStream), ProcessingError>;
+
+fn archive_members(
+    kind: archive::ArchiveKind,
+    builder: OutputBuilder,
+    stream: InputStream,
+) -> Box<dyn Iterator<Item = Input>> {
+    let path = builder.path().to_path_buf();
+    let members: Box<dyn Iterator<Item = anyhow::Result<archive::Member>>> = match kind {
+        archive::ArchiveKind::Tar => {
+            Box::new(archive::tar_members(move || stream.into_reader()))
+        }
+        archive::ArchiveKind::Zip => {
+            let members = match stream {
+                InputStream::File(f) => archive::zip_members(f).map(|v| {
+                    Box::new(v) as Box<dyn Iterator<Item = anyhow::Result<archive::Member>>>
+                }),
+                InputStream::Path(p) => std::fs::File::open(p)
+                    .map_err(anyhow::Error::from)
+                    .and_then(archive::zip_members)
+                    .map(|v| Box::new(v) as Box<dyn Iterator<Item = _>>),
+                InputStream::Bytes(b) => archive::zip_members(io::Cursor::new(b))
+                    .map(|v| Box::new(v) as Box<dyn Iterator<Item = _>>),
+                InputStream::Stdin => Err(anyhow!("zip archives can't be read from stdin")),
+            };
+            match members {
+                Ok(m) => m,
+                Err(e) => return Box::new(std::iter::once(Err(FileIO { path, source: e }))),
             }
         }
+    };
+    Box::new(members.flat_map(move |member| match member {
+        Ok(member) => expand_input(Ok((
+            builder.archive_member(&member.path, member.modified),
+            InputStream::Bytes(member.data),
+        ))),
+        Err(e) => Box::new(std::iter::once(Err(FileIO {
+            path: path.clone(),
+            source: e,
+        }))),
+    }))
+}
+
+// Archives and WARC files hold many documents, split them into one input per document
+fn expand_input(input: Input) -> Box<dyn Iterator<Item = Input>> {
+    let kind = match &input {
+        Ok((builder, _)) => archive::ArchiveKind::from_path(builder.path()),
+        Err(_) => None,
+    };
+    match (kind, input) {
+        (Some(kind), Ok((builder, stream))) => archive_members(kind, builder, stream),
+        (None, Ok((builder, stream))) if builder.kind() == InputKind::WARC => {
+            let path = builder.path().to_path_buf();
+            let reader = match stream.into_reader() {
+                Ok(r) => r,
+                Err(e) => return Box::new(std::iter::once(Err(FileIO { path, source: e }))),
+            };
+            Box::new(
+                warc::WarcReader::new(reader)
+                    .filter_map(move |record| {
+                        let record = match record {
+                            Ok(r) => r,
+                            Err(e) => {
+                                return Some(Err(ProcessingError::WarcParseError {
+                                    path: path.clone(),
+                                    source: e,
+                                }))
+                            }
+                        };
+                        if !record.is_response() {
+                            return None;
+                        }
+                        let http = record.http_response()?;
+                        if !http.is_html() {
+                            return None;
+                        }
+                        let mut b = builder.clone();
+                        b.warc(Some(WarcMeta {
+                            target_uri: record.target_uri().map(String::from),
+                            date: record.date().map(String::from),
+                            record_id: record.record_id().map(String::from),
+                        }));
+                        Some(
+                            http.into_body()
+                                .map(|body| (b, InputStream::Bytes(body)))
+                                .map_err(|e| ProcessingError::WarcParseError {
+                                    path: path.clone(),
+                                    source: e,
+                                }),
+                        )
+                    }),
+            )
+        }
+        (_, other) => Box::new(std::iter::once(other)),
     }
 }
 
 enum Message {
     Job(ParseOutput),
-    ErrorLog(PathBuf),
+    // a document with the same sha1 was already output
+    Duplicate {
+        path: PathBuf,
+        name: String,
+        sha1: String,
+        first: String,
+    },
+    ErrorLog(ErrorRecord),
     Stop,
 }
 
@@ -99,14 +193,27 @@ enum ProcessingError {
         #[source]
         source: anyhow::Error,
     },
+    #[error("failed to read warc record in {path}: {source}")]
+    WarcParseError {
+        path: PathBuf,
+        #[source]
+        source: anyhow::Error,
+    },
     #[error("failed to send job to channel: {path}")]
     JobSendError { path: PathBuf },
     #[error("found no match in {path}: {source}")]
     ParlerParseError {
         path: PathBuf,
+        sha1: String,
         #[source]
         source: unhtml::Error,
     },
+    #[error("{path} failed validation: {violations}")]
+    ValidationError {
+        path: PathBuf,
+        sha1: String,
+        violations: Violations,
+    },
     #[error("processing error: {0:?}")]
     Other(#[from] anyhow::Error),
     #[error("error during directory traversal: {0:?}")]
@@ -114,10 +221,25 @@ enum ProcessingError {
 }
 
 impl ProcessingError {
+    /// Name of the variant, used in the json fail log and the run statistics
+    fn kind(&self) -> &'static str {
+        match self {
+            ProcessingError::FileIO { .. } => "FileIO",
+            ProcessingError::HTMLParseError { .. } => "HTMLParseError",
+            ProcessingError::WarcParseError { .. } => "WarcParseError",
+            ProcessingError::JobSendError { .. } => "JobSendError",
+            ProcessingError::ParlerParseError { .. } => "ParlerParseError",
+            ProcessingError::ValidationError { .. } => "ValidationError",
+            ProcessingError::Other(_) => "Other",
+            ProcessingError::Traversal(_) => "Traversal",
+        }
+    }
     fn path(&self) -> Option<&PathBuf> {
         match self {
             ProcessingError::ParlerParseError { path, .. }
+            | ProcessingError::ValidationError { path, .. }
             | ProcessingError::HTMLParseError { path, .. }
+            | ProcessingError::WarcParseError { path, .. }
             | ProcessingError::JobSendError { path }
             | ProcessingError::FileIO { path, .. } => Some(path),
             _ => None,
@@ -143,7 +265,10 @@ impl From<crossbeam_channel::SendError<Message>> for ProcessingError {
             Message::Job(out)=> {
                 ProcessingError::JobSendError { path: out.meta.file.map_or_else(|| PathBuf::from("-"), |v| v.path) }
             },
-            Message::ErrorLog(path) => ProcessingError::JobSendError { path },
+            Message::Duplicate { path, .. } => ProcessingError::JobSendError { path },
+            Message::ErrorLog(record) => ProcessingError::JobSendError {
+                path: record.path.map_or_else(|| PathBuf::from("-"), PathBuf::from),
+            },
             Message::Stop => {
                 ProcessingError::Other(anyhow!("failed to send stop message to channel"))
             }
@@ -154,31 +279,83 @@ impl From<crossbeam_channel::SendError<Message>> for ProcessingError {
 type BufFile = io::BufWriter<std::fs::File>;
 fn main() -> anyhow::Result<()> {
     let mut app = args::parse_args();
-    let config = Configuration::from(app.clone().get_matches());
+    let matches = app.clone().get_matches();
+    if matches.subcommand_matches("schema").is_some() {
+        let stdout = io::stdout();
+        let mut lock = stdout.lock();
+        serde_json::to_writer_pretty(&mut lock, &parler_indexer::output_schema())?;
+        writeln!(&mut lock)?;
+        return Ok(());
+    }
+    let config = Configuration::from(matches);
+    // ordered and watching runs hand out the documents from a thread of the pool, it mostly
+    // waits and doesn't count towards --jobs
+    let dispatcher = usize::from(config.ordered() || config.watch());
+    if config.jobs().is_some() || dispatcher > 0 {
+        let jobs = config
+            .jobs()
+            .or_else(|| std::thread::available_parallelism().ok().map(|v| v.get()))
+            .unwrap_or(1);
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs + dispatcher)
+            .build_global()
+            .context("failed to start the thread pool")?;
+    }
     let source = config.source();
     let compact = config.compact();
     if !(config.path_count() > 0 || config.should_parse_stdin()) {
         app.print_long_help()?;
     }
+    if config.resume() && config.parquet_path().is_some() {
+        bail!("--resume can't be used with --parquet, the file is rewritten from scratch and would only hold the new pages");
+    }
+    if config.resume() && config.tables_dir().is_some() {
+        bail!("--resume can't be used with --tables-dir, the tables are rewritten from scratch and would only hold the new pages");
+    }
+    if config.resume() && config.output_format() == OutputFormat::MergedPosts {
+        bail!("--resume can't be used with merged-posts output, posts are only written once all input has been read");
+    }
+    match (config.output_dir(), config.output_format()) {
+        (Some(_), OutputFormat::Normalized) => {
+            bail!("--output-dir can't be used with normalized output, the tables go to --tables-dir")
+        }
+        (Some(_), OutputFormat::Mirror) => {
+            bail!("--output-dir can't be used with mirror output, the files go to --mirror-dir")
+        }
+        _ => {}
+    }
+    // a resumed run adds its failures to the ones of the runs before it
     let fail_log = config.fail_path().map(|v| -> Result<BufFile> {
-        std::fs::File::create(v)
-            .map_err(|e| {
-                anyhow::Error::from(e).context(format!(
-                    "failed to open failure log file {}",
-                    grep_cli::escape_os(v.as_os_str())
-                ))
-            })
-            .map(BufWriter::new)
+        (if config.resume() {
+            open_append(v)
+        } else {
+            std::fs::File::create(v)
+        })
+        .map_err(|e| {
+            anyhow::Error::from(e).context(format!(
+                "failed to open failure log file {}",
+                grep_cli::escape_os(v.as_os_str())
+            ))
+        })
+        .map(BufWriter::new)
     });
-    let success_log = config.success_path().map(|v| -> Result<BufFile> {
-        std::fs::File::create(v)
-            .map_err(|e| {
-                anyhow::Error::from(e).context(format!(
-                    "failed to open failure log file {}",
-                    grep_cli::escape_os(v.as_os_str())
-                ))
-            })
-            .map(BufWriter::new)
+    // read what's already done before the log gets appended to
+    let resume = match (config.resume(), config.success_path()) {
+        (true, Some(path)) => Some(ResumeLog::open(path)?),
+        _ => None,
+    };
+    let success_log = config.success_path().map(|v| -> Result<SuccessLog> {
+        (if resume.is_some() {
+            SuccessLog::append(v)
+        } else {
+            SuccessLog::create(v)
+        })
+        .map_err(|e| {
+            anyhow::Error::from(e).context(format!(
+                "failed to open success log file {}",
+                grep_cli::escape_os(v.as_os_str())
+            ))
+        })
     });
     if let Some(Err(e)) = success_log {
         bail!(e);
@@ -187,48 +364,183 @@ fn main() -> anyhow::Result<()> {
         bail!(e);
     }
     let should_parse_stdin = config.should_parse_stdin();
+    let force_warc = config.warc();
+    let parse_page: fn(&parler_indexer::Html) -> parler_indexer::Result<parler_indexer::ParlerPage> =
+        if config.lenient() {
+            parler_indexer::parse_page_html_lenient
+        } else {
+            parler_indexer::parse_page_html
+        };
+    let required_fields = config.required_fields();
+    let input_kind = move |path: &Path| {
+        if force_warc {
+            InputKind::WARC
+        } else {
+            InputKind::from_path(path)
+        }
+    };
 
     let send_errors = fail_log.is_some();
+    let error_log_format = config.error_log_format();
+    let output_format = config.output_format();
+    let es_index = config.es_index().to_string();
+    let es_split_posts = config.es_split_posts();
+    let tables = match (output_format, config.tables_dir()) {
+        (OutputFormat::Normalized, Some(dir)) => {
+            Some(RelationalSink::create(dir, config.table_format())?)
+        }
+        _ => None,
+    };
+    let sqlite = config.sqlite_path().map(|v| SqliteSink::open(v)).transpose()?;
+    let parquet = config
+        .parquet_path()
+        .map(|v| {
+            ParquetSink::create(v, config.parquet_compression(), config.parquet_row_group_pages())
+        })
+        .transpose()?;
+    let mirror_dir = match output_format {
+        OutputFormat::Mirror => config.mirror_dir(),
+        _ => None,
+    };
+    let mirror = mirror_dir.map(|v| MirrorSink::new(v, compact));
+    let shards = config
+        .output_dir()
+        .map(|v| {
+            ShardWriter::create(v, "ndjson", config.shard_compression(), config.shard_limits())
+        })
+        .transpose()?;
+
+    let seen_hashes = match (config.dedupe(), config.dedupe_hashes()) {
+        (true, Some(path)) => Some(SeenHashes::open(path)?),
+        (true, None) => Some(SeenHashes::default()),
+        _ => None,
+    };
+    let hash_log = config
+        .dedupe_hashes()
+        .map(|v| {
+            HashLog::append(v)
+                .with_context(|| format!("failed to open hash log {}", v.display()))
+        })
+        .transpose()?;
+    let duplicates_log = config
+        .duplicates_log()
+        .map(|v| {
+            File::create(v)
+                .map(BufWriter::new)
+                .with_context(|| format!("failed to open duplicates log {}", v.display()))
+        })
+        .transpose()?;
 
-    let (tx, rx) = crossbeam_channel::unbounded::<Message>();
+    let resume = resume.as_ref();
+    let seen_hashes = seen_hashes.as_ref();
+
+    let run_stats = Arc::new(Stats::default());
+    let stats = run_stats.as_ref();
+    // without recursion every line of the path file is one input, that's enough for an ETA
+    let total = match config.path_file() {
+        Some(path) if !config.recursive() => File::open(path)
+            .map(|v| BufReader::new(v).lines().count() as u64)
+            .ok(),
+        _ => None,
+    };
+    // the first signal only stops new work, everything already parsed is written and the logs
+    // are flushed as usual
+    let run_shutdown = Shutdown::on_signals()?;
+    let shutdown = &run_shutdown;
+    // watching starts before the walk so nothing closed during it is missed, a file can show up
+    // in both though
+    let progress = if config.progress() {
+        Some(Progress::spawn(run_stats.clone(), total))
+    } else {
+        None
+    };
+    let stderr = progress.as_ref().map(Progress::stderr).unwrap_or_default();
+    let watcher = if config.watch() {
+        Some(Watcher::new(config.paths(), run_shutdown.clone(), stderr.clone())?)
+    } else {
+        None
+    };
+
+    // WARC records all share the path of the WARC file and stdin is always "-",
+    // those can only be skipped by sha1
+    let already_done = move |path: &Path, kind: InputKind| {
+        let done = resume.is_some_and(|r| {
+            kind != InputKind::WARC && path != Path::new("-") && r.contains_path(path)
+        });
+        if done {
+            stats.skipped();
+        }
+        done
+    };
+
+    // parsing threads block on send while the writer catches up
+    let (tx, rx) = bounded::<Message>(config.queue_size());
     
-    let files = std::iter::once_with(|| {
+    let stdin_input = std::iter::once_with(|| {
         if should_parse_stdin {
-            let mut builder = OutputBuilder::new(InputKind::HTML, PathBuf::from("-"));
-            builder.source(config.source().map(String::from));
+            stats.discovered();
+            let path = PathBuf::from("-");
+            let mut builder = OutputBuilder::new(input_kind(&path), path);
+            builder
+                .source(config.source().map(String::from))
+                .reference_dt(config.reference_time());
             Some(Ok((builder, InputStream::Stdin)))
         } else {
             None
         }
     })
-    .flatten()
-    .par_bridge()
-    .chain(
-        config
-            .walk_paths()
-            .par_bridge()
-            .filter_map(|v| match v {
-                Ok(de) if de.file_type().is_file() => Some(Ok(de)),
-                Ok(_) => None,
-                Err(e) => Some(Err(ProcessingError::from(e))),
-            })
-            .map(|v| {
-                v.and_then(|v| {
-                    let path = v.path();
-                    let mut builder = OutputBuilder::new(InputKind::HTML, path.into());
-                    builder.entry(&v)
-                        .source(config.source().map(String::from));
-                    std::fs::File::open(path)
-                        .map(move |v| (builder, InputStream::File(v)))
-                        .map_err(|e| FileIO {
-                            path: path.to_path_buf(),
-                            source: e.into(),
-                        })
-                })
-            }),
-    )
-    .map(|res| {
-        res.and_then(|(mut b, mut input)| {
+    .flatten();
+    // `depth` is how far below the walked directory the entry is
+    let open_file = |v: Result<(DirEntry, usize), walkdir::Error>| -> Option<Input> {
+        if shutdown.is_stopping() {
+            return None;
+        }
+        let (entry, depth) = match v {
+            Ok((de, depth)) if de.file_type().is_file() => {
+                stats.discovered();
+                let path = de.path();
+                let is_archive = archive::ArchiveKind::from_path(path).is_some();
+                if !is_archive && already_done(path, input_kind(path)) {
+                    return None;
+                }
+                (de, depth)
+            }
+            Ok(_) => return None,
+            Err(e) => return Some(Err(ProcessingError::from(e))),
+        };
+        let path = entry.path();
+        let mut builder = OutputBuilder::new(input_kind(path), path.into());
+        builder.entry_at(&entry, depth)
+            .source(config.source().map(String::from))
+            .reference_dt(config.reference_time());
+        // make style, archives and WARC files turn into many outputs and are always read
+        if let Some(dir) = mirror_dir {
+            let is_page = archive::ArchiveKind::from_path(path).is_none()
+                && builder.kind() == InputKind::HTML;
+            if is_page && is_up_to_date(&mirror_path(dir, path, builder.relative_path()), path) {
+                stats.skipped();
+                return None;
+            }
+        }
+        Some(
+            std::fs::File::open(path)
+                .map(move |v| (builder, InputStream::File(v)))
+                .map_err(|e| FileIO {
+                    path: path.to_path_buf(),
+                    source: e.into(),
+                }),
+        )
+    };
+    let parse_input = |res: Input| -> Option<Result<Message, ProcessingError>> {
+        if shutdown.is_stopping() {
+            return None;
+        }
+        if let Ok((b, _)) = &res {
+            if already_done(b.path(), b.kind()) {
+                return None;
+            }
+        }
+        res.and_then(|(mut b, input)| {
             input
                 .read_document()
                 .map_err(|e| ProcessingError::HTMLParseError {
@@ -236,24 +548,57 @@ fn main() -> anyhow::Result<()> {
                     source: e,
                 })
                 .and_then(
-                    |(sha1, v)| -> Result<ParseOutput, ProcessingError> {
-                        let sel = Selector::parse(":root").unwrap();
-                        b.sha1(sha1);
-                        v.select(&sel)
-                            .element()
-                            .map_err(|e| ProcessingError::ParlerParseError {
+                    |(sha1, v)| -> Result<Option<Message>, ProcessingError> {
+                        if resume.is_some_and(|r| r.contains_sha1(&sha1)) {
+                            stats.skipped();
+                            return Ok(None);
+                        }
+                        if let Some(seen) = seen_hashes {
+                            let name = document_name(b.path(), b.warc_record_id());
+                            if let Some(first) = seen.first_seen(&sha1, name.clone()) {
+                                return Ok(Some(Message::Duplicate {
+                                    path: b.path().to_path_buf(),
+                                    name,
+                                    sha1,
+                                    first,
+                                }));
+                            }
+                        }
+                        b.sha1(sha1.clone());
+                        let page = parse_page(&v).map_err(|e| match e {
+                            parler_indexer::Error::Parse(source) => {
+                                ProcessingError::ParlerParseError {
+                                    path: b.path().to_path_buf(),
+                                    sha1: sha1.clone(),
+                                    source,
+                                }
+                            }
+                            e => ProcessingError::HTMLParseError {
                                 path: b.path().to_path_buf(),
-                                source: e,
-                            })
-                            .map(move |v| b.build(v).unwrap())
+                                source: e.into(),
+                            },
+                        })?;
+                        if let Some(rules) = required_fields {
+                            let violations = validate(&page, rules);
+                            if !violations.is_empty() {
+                                return Err(ProcessingError::ValidationError {
+                                    path: b.path().to_path_buf(),
+                                    sha1,
+                                    violations: Violations(violations),
+                                });
+                            }
+                        }
+                        Ok(Some(Message::Job(b.build(page).unwrap())))
                     },
                 )
-                .map(|v| Message::Job(v))
         })
-    });
+        .transpose()
+    };
 
+    let writer_shutdown = run_shutdown.clone();
     let writer = std::thread::spawn(move || -> Result<()> {
- 
+        // when writing fails the parsing threads stop instead of filling a queue nobody reads
+        let _stop_parsing = writer_shutdown.cancel_on_drop();
         let mut success_log = match success_log {
             Some(Ok(l)) => Some(l),
             _ => None,
@@ -262,33 +607,72 @@ fn main() -> anyhow::Result<()> {
             Some(Ok(l)) => Some(l),
             _ => None,
         };
+        let mut hash_log = hash_log;
+        let mut duplicates_log = duplicates_log;
         let stdout = io::stdout();
-        
+        let out: Box<dyn DocumentWrite> = match shards {
+            Some(shards) => Box::new(shards),
+            None => Box::new(stdout.lock()),
+        };
+        let mut sinks: Vec<Box<dyn Sink>> = vec![match output_format {
+            OutputFormat::Json => Box::new(JsonSink::new(out, compact)),
+            OutputFormat::EsBulk => Box::new(EsBulkSink::new(out, es_index, es_split_posts)),
+            OutputFormat::Normalized => Box::new(tables.expect("normalized output requires --tables-dir")),
+            OutputFormat::MergedPosts => Box::new(MergeSink::new(out, compact)),
+            OutputFormat::Mirror => Box::new(mirror.expect("mirror output requires --mirror-dir")),
+        }];
+        if let Some(sqlite) = sqlite {
+            sinks.push(Box::new(sqlite));
+        }
+        if let Some(parquet) = parquet {
+            sinks.push(Box::new(parquet));
+        }
 
         loop {
             let result = rx.recv()?;
-            let mut lock = stdout.lock();
             match result {
                 Message::Job(page) => {
-                    
-                        (if compact {
-                            serde_json::to_writer
-                        } else {
-                            serde_json::to_writer_pretty
-                        })(&mut lock, &page)
-                    .context("error while writing output")?;
-                    writeln!(&mut lock).context("error while writing output")?;
+                    for sink in sinks.iter_mut() {
+                        sink.write(&page)?;
+                    }
+                    if let Some(log) = hash_log.borrow_mut() {
+                        let path = page.meta.file.as_ref().map_or(Path::new("-"), |v| v.path.as_path());
+                        let record_id = page.meta.warc.as_ref().and_then(|v| v.record_id.as_deref());
+                        log.push(&page.meta.sha1, &document_name(path, record_id))
+                            .context("error while writing to hash log")?;
+                    }
                     if let Some(log) = success_log.borrow_mut() {
-                        if let Some(meta) = page.meta.file { 
-                            writeln!(log, "{}", grep_cli::escape_os(meta.path.as_os_str()))
-                            .context("error while writing to success log")?;
+                        if let Some(meta) = &page.meta.file {
+                            log.push(&meta.path, &page.meta.sha1);
+                        }
+                        if log.should_sync() {
+                            for sink in sinks.iter_mut() {
+                                sink.flush()?;
+                            }
+                            if let Some(hash_log) = hash_log.borrow_mut() {
+                                hash_log.flush().context("error while writing to hash log")?;
+                            }
+                            log.sync().context("error while writing to success log")?;
                         }
                     }
                     continue;
                 }
-                Message::ErrorLog(ref path) => {
+                Message::Duplicate { path, name, sha1, first } => {
+                    if let Some(log) = duplicates_log.borrow_mut() {
+                        writeln!(log, "{}\t{}\t{}", name, sha1, first)
+                            .context("error while writing to duplicates log")?;
+                    }
+                    // dropping it was the whole job, a resumed run doesn't have to look at it again
+                    if let Some(log) = success_log.borrow_mut() {
+                        if path != Path::new("-") {
+                            log.push(&path, &sha1);
+                        }
+                    }
+                }
+                Message::ErrorLog(ref record) => {
                     if let Some(fail_log) = fail_log.borrow_mut() {
-                            writeln!(fail_log, "{}", grep_cli::escape_os(path.as_os_str()))
+                        record
+                            .write(fail_log, error_log_format)
                             .context("error while writing to fail log")?;
                     }
                 }
@@ -296,31 +680,129 @@ fn main() -> anyhow::Result<()> {
                 Message::Stop => break,
             }
         }
+        for sink in sinks.iter_mut() {
+            sink.finish()?;
+        }
         fail_log.and_then(|mut v| v.flush().ok());
-        success_log.and_then(|mut v| v.flush().ok());
+        if let Some(log) = hash_log.borrow_mut() {
+            log.flush().context("error while writing to hash log")?;
+        }
+        if let Some(log) = duplicates_log.borrow_mut() {
+            log.flush().context("error while writing to duplicates log")?;
+        }
+        if let Some(log) = success_log.borrow_mut() {
+            log.sync().context("error while writing to success log")?;
+        }
         Ok(())
     });
 
-    let _ = files
-        .map(|v| v.map(|v| tx.send(v).map_err(ProcessingError::from)))
-        .all(|result| {
-            if let Err(e) = result {
-                eprintln!("{}", e);
-                if let (true, Some(path)) = (send_errors, e.path()) {
-                    if let Err(_) = tx.send(Message::ErrorLog(path.into())) {
-                        eprintln!("{}", anyhow!("failure while sending error log job"));
-                        return false;
-                    }
-                }
+    // Results that were already produced are passed on even when the run is stopping, only
+    // new inputs aren't taken anymore. Returns false once it's stopping.
+    let handle = |result: Result<Message, ProcessingError>| {
+        if shutdown.is_cancelled() {
+            return false;
+        }
+        let result = result.map(|v| {
+            match v {
+                Message::Job(_) => stats.parsed(),
+                Message::Duplicate { .. } => stats.duplicate(),
+                _ => {}
+            }
+            tx.send(v).map_err(ProcessingError::from)
+        });
+        if let Err(e) = result {
+            stats.failed(e.kind());
+            stderr.print(&e);
+            if send_errors && tx.send(Message::ErrorLog(ErrorRecord::from(&e))).is_err() {
+                stderr.print(anyhow!("failure while sending error log job"));
+                return false;
+            }
 
-                if let ProcessingError::JobSendError { .. } = e {
-                    return false;
+            // the writer is gone, nothing more can be written
+            if let ProcessingError::JobSendError { .. } = e {
+                shutdown.cancel();
+                return false;
+            }
+        }
+        !shutdown.is_stopping()
+    };
+
+    let walked = config
+        .walk_paths()
+        .take_while(move |_| !shutdown.is_stopping())
+        .map(|v| {
+            v.map(|v| {
+                let depth = v.depth();
+                (v, depth)
+            })
+        });
+    // files that arrive while the walk is still going wait in the channel
+    let arrivals = watcher.map(Watcher::spawn);
+    // archives and WARC files can hold a lot of documents, don't read the rest of them
+    let expand = |input: Input| expand_input(input).take_while(move |_| !shutdown.is_stopping());
+
+    if config.ordered() {
+        // documents are numbered in walk order, archive and WARC members in the order they're
+        // stored, and spread over the threads one by one. The walk waits while too many of
+        // them are held back by an earlier one that isn't done yet.
+        let reorder = SharedReorderBuffer::new(config.queue_size());
+        rayon::scope(|scope| {
+            let documents = stdin_input
+                .chain(walked.chain(arrivals.into_iter().flatten()).filter_map(open_file))
+                .flat_map(expand)
+                .enumerate();
+            for (seq, input) in documents {
+                if !reorder.reserve(seq, || shutdown.is_stopping()) {
+                    break;
                 }
+                let (reorder, parse_input, handle) = (&reorder, &parse_input, &handle);
+                scope.spawn(move |_| {
+                    reorder.push(seq, parse_input(input), |result| {
+                        if let Some(result) = result {
+                            handle(result);
+                        }
+                    })
+                });
             }
-            true
         });
+    } else {
+        let _ = stdin_input
+            .par_bridge()
+            .chain(walked.par_bridge().filter_map(open_file))
+            .flat_map_iter(expand)
+            .filter_map(parse_input)
+            .all(handle);
+        // par_bridge over the blocking watcher would keep the idle threads spinning, arrivals
+        // are handed to the pool one by one instead
+        if let Some(arrivals) = arrivals {
+            rayon::scope(|scope| {
+                for input in arrivals.iter().filter_map(open_file) {
+                    let (expand, parse_input, handle) = (&expand, &parse_input, &handle);
+                    scope.spawn(move |_| {
+                        let _ = expand(input).filter_map(parse_input).all(handle);
+                    });
+                }
+            });
+        }
+    }
 
     let res = tx.send(Message::Stop);
-    writer.join().unwrap();
-    res.map_err(|e| anyhow!(e))
+    let written = writer.join().expect("writer thread panicked");
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    let summary = stats.summary();
+    if config.progress() {
+        summary.print(&mut io::stderr())?;
+    }
+    if let Some(path) = config.stats_json() {
+        summary.write_json(path)?;
+    }
+    // a failed writer is why the send failed, its error is the one worth reporting
+    written?;
+    res.map_err(|e| anyhow!(e))?;
+    match run_shutdown.exit_code(summary.failed) {
+        0 => Ok(()),
+        code => std::process::exit(code),
+    }
 }
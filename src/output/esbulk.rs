@@ -0,0 +1,123 @@
+use super::{post_id, DocumentWrite, Sink};
+use anyhow::{Context, Result};
+use parler_indexer::{ParlerPost, ParseOutput, ScrapeMeta};
+use serde::Serialize;
+
+// Elasticsearch bulk API, an action line followed by the document
+// https://www.elastic.co/guide/en/elasticsearch/reference/current/docs-bulk.html
+
+#[derive(Serialize)]
+struct Action<'a> {
+    index: ActionMeta<'a>,
+}
+
+#[derive(Serialize)]
+struct ActionMeta<'a> {
+    #[serde(rename = "_index")]
+    index: &'a str,
+    #[serde(rename = "_id")]
+    id: &'a str,
+}
+
+#[derive(Serialize)]
+struct PostDocument<'a> {
+    #[serde(rename = "__meta")]
+    meta: &'a ScrapeMeta,
+    #[serde(flatten)]
+    post: &'a ParlerPost,
+}
+
+pub struct EsBulkSink<W> {
+    out: W,
+    index: String,
+    split_posts: bool,
+}
+
+impl<W: DocumentWrite> EsBulkSink<W> {
+    pub fn new(out: W, index: String, split_posts: bool) -> Self {
+        Self {
+            out,
+            index,
+            split_posts,
+        }
+    }
+
+    fn write_document<T: Serialize>(&mut self, id: &str, doc: &T) -> Result<()> {
+        let action = Action {
+            index: ActionMeta {
+                index: &self.index,
+                id,
+            },
+        };
+        serde_json::to_writer(&mut self.out, &action).context("error while writing output")?;
+        writeln!(&mut self.out).context("error while writing output")?;
+        // the bulk api is newline delimited, never pretty print
+        serde_json::to_writer(&mut self.out, doc).context("error while writing output")?;
+        writeln!(&mut self.out).context("error while writing output")?;
+        self.out.end_document().context("error while writing output")
+    }
+}
+
+impl<W: DocumentWrite> Sink for EsBulkSink<W> {
+    fn write(&mut self, output: &ParseOutput) -> Result<()> {
+        if !self.split_posts {
+            return self.write_document(&output.meta.sha1, output);
+        }
+        for (i, post) in output.page.posts.iter().enumerate() {
+            let (id, _) = post_id(&output.meta, i, post);
+            let doc = PostDocument {
+                meta: &output.meta,
+                post,
+            };
+            self.write_document(&id, &doc)?;
+        }
+        Ok(())
+    }
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush().context("error while writing output")
+    }
+    fn finish(&mut self) -> Result<()> {
+        self.out.close().context("error while writing output")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tests::{example_output, EXAMPLE_POST_ID};
+    use serde_json::{json, Value};
+
+    fn lines(split_posts: bool) -> Vec<Value> {
+        let mut sink = EsBulkSink::new(Vec::new(), "parler".into(), split_posts);
+        sink.write(&example_output("abc")).unwrap();
+        sink.finish().unwrap();
+        String::from_utf8(sink.out)
+            .unwrap()
+            .lines()
+            .map(|v| serde_json::from_str(v).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn writes_action_and_document_pairs() {
+        let lines = lines(false);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], json!({"index": {"_index": "parler", "_id": "abc"}}));
+        assert_eq!(lines[1]["__meta"]["sha1"], "abc");
+        assert_eq!(lines[1]["posts"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn splits_posts() {
+        let lines = lines(true);
+        assert_eq!(lines.len(), 4);
+        // no id on the page, keyed by position
+        assert_eq!(lines[0], json!({"index": {"_index": "parler", "_id": "abc-0"}}));
+        assert_eq!(lines[1]["post_id"], Value::Null);
+        assert_eq!(lines[2]["index"]["_id"], EXAMPLE_POST_ID);
+        assert_eq!(lines[3]["post_id"], EXAMPLE_POST_ID);
+        assert_eq!(lines[3]["__meta"]["sha1"], "abc");
+        assert_eq!(lines[3]["comments"][0]["body"], "first comment");
+        assert!(lines[3].get("posts").is_none());
+    }
+}
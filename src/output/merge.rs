@@ -0,0 +1,352 @@
+use super::{post_id, DocumentWrite, Sink};
+use anyhow::{Context, Result};
+use parler_indexer::parse::post::PostCardType;
+use parler_indexer::{Comment, ParlerPost, ParseOutput};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+
+// The same post shows up on its own page, in profile feeds and in echoes. Posts are collected
+// by post id and written once all input has been read, so everything is held in memory.
+// Feeds don't show the post id, those copies are matched up by author, body and media.
+
+#[derive(Debug, Serialize)]
+struct MergeMeta {
+    post_id: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    post_id_synthetic: bool,
+    /// sha1 of every page the post was seen on, in the order they were read
+    page_sha1s: Vec<String>,
+    /// Scrape time of the page the counts and cards were taken from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latest_dt: Option<u64>,
+    /// sha1 of the page the counts and cards were taken from
+    latest_sha1: String,
+}
+
+#[derive(Debug, Serialize)]
+struct MergedPost {
+    #[serde(rename = "__merge")]
+    meta: MergeMeta,
+    #[serde(flatten)]
+    post: ParlerPost,
+}
+
+impl MergedPost {
+    fn observe(&mut self, sha1: &str, seen_dt: Option<u64>, mut post: ParlerPost) {
+        if !self.meta.page_sha1s.iter().any(|v| v == sha1) {
+            self.meta.page_sha1s.push(sha1.into());
+        }
+        // pages without a scrape time never beat ones with it. Every page has the same time
+        // with --reference-time, ties go to the higher sha1 so the read order doesn't matter.
+        if (seen_dt, sha1) >= (self.meta.latest_dt, self.meta.latest_sha1.as_str()) {
+            std::mem::swap(&mut self.post, &mut post);
+            self.meta.latest_dt = seen_dt;
+            self.meta.latest_sha1 = sha1.into();
+        }
+        // `post` is the older copy now, only its comments are still of interest
+        merge_comments(&mut self.post.comments, post.comments);
+    }
+}
+
+// Author and a hash of the body and media of the post's own card (the echoed one for echoes),
+// None when there's nothing to tell it apart by. The timestamp is left out, both the relative
+// one and approx_created_at move with the scrape time of each page.
+fn content_key(post: &ParlerPost) -> Option<String> {
+    let card_of = |kind: PostCardType| post.cards.iter().find(|v| v.kind == kind);
+    let card = card_of(PostCardType::EchoRoot)
+        .or_else(|| card_of(PostCardType::Post))
+        .or_else(|| post.cards.first())?;
+    let author = card.author.as_ref()?;
+    let mut hash = Sha1::new();
+    hash.update(card.body.as_ref().map_or("", |v| v.as_ref()));
+    let media = card.media_container.as_ref().map(|v| v.media_items.as_slice());
+    for item in media.unwrap_or_default() {
+        let url = item.source.as_ref().map_or("", |v| v.url_raw.as_str());
+        hash.update(b"\0");
+        hash.update(url);
+    }
+    Some(format!("{}\t{:x}", author.username, hash.finalize()))
+}
+
+// Comments without an id are told apart by author and body
+fn comment_key(comment: &Comment) -> String {
+    match &comment.comment_id {
+        Some(id) => id.as_ref().to_string(),
+        None => format!(
+            "{}\t{}",
+            comment.author.as_ref().map_or("", |v| v.username.as_str()),
+            comment.body.as_ref().map_or("", |v| v.as_ref())
+        ),
+    }
+}
+
+// Adds the comments that only appear in `older`, comments in both keep the newer copy
+// with the union of their replies
+fn merge_comments(newer: &mut Vec<Comment>, older: Vec<Comment>) {
+    let mut index: HashMap<String, usize> = newer
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (comment_key(v), i))
+        .collect();
+    for comment in older {
+        let key = comment_key(&comment);
+        match index.get(&key) {
+            Some(&i) => {
+                if let Some(replies) = comment.replies {
+                    merge_comments(newer[i].replies.get_or_insert_with(Vec::new), replies);
+                }
+            }
+            None => {
+                index.insert(key, newer.len());
+                newer.push(comment);
+            }
+        }
+    }
+}
+
+pub struct MergeSink<W> {
+    out: W,
+    compact: bool,
+    posts: Vec<MergedPost>,
+    index: HashMap<String, usize>,
+    by_content: HashMap<String, usize>,
+}
+
+impl<W: DocumentWrite> MergeSink<W> {
+    pub fn new(out: W, compact: bool) -> Self {
+        Self {
+            out,
+            compact,
+            posts: Vec::new(),
+            index: HashMap::new(),
+            by_content: HashMap::new(),
+        }
+    }
+}
+
+impl<W: DocumentWrite> Sink for MergeSink<W> {
+    fn write(&mut self, output: &ParseOutput) -> Result<()> {
+        let sha1 = &output.meta.sha1;
+        let seen_dt = output.meta.reference_dt;
+        for (i, post) in output.page.posts.iter().enumerate() {
+            let (id, synthetic) = post_id(&output.meta, i, post);
+            let content = content_key(post);
+            // a copy without an id goes with any post of the same content, one with an id only
+            // takes over a post that had none so far
+            let posts = &self.posts;
+            let found = self.index.get(&id).copied().or_else(|| {
+                content
+                    .as_ref()
+                    .and_then(|v| self.by_content.get(v).copied())
+                    .filter(|&i| synthetic || posts[i].meta.post_id_synthetic)
+            });
+            match found {
+                Some(i) => {
+                    let merged = &mut self.posts[i];
+                    if !synthetic && merged.meta.post_id_synthetic {
+                        merged.meta.post_id = id.clone();
+                        merged.meta.post_id_synthetic = false;
+                        self.index.insert(id, i);
+                    }
+                    merged.observe(sha1, seen_dt, post.clone());
+                }
+                None => {
+                    self.index.insert(id.clone(), self.posts.len());
+                    if let Some(content) = content {
+                        self.by_content.entry(content).or_insert(self.posts.len());
+                    }
+                    self.posts.push(MergedPost {
+                        meta: MergeMeta {
+                            post_id: id,
+                            post_id_synthetic: synthetic,
+                            page_sha1s: vec![sha1.clone()],
+                            latest_dt: seen_dt,
+                            latest_sha1: sha1.clone(),
+                        },
+                        post: post.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+    fn finish(&mut self) -> Result<()> {
+        for post in self.posts.drain(..) {
+            (if self.compact {
+                serde_json::to_writer
+            } else {
+                serde_json::to_writer_pretty
+            })(&mut self.out, &post)
+            .context("error while writing output")?;
+            writeln!(&mut self.out).context("error while writing output")?;
+            self.out.end_document().context("error while writing output")?;
+        }
+        self.index.clear();
+        self.by_content.clear();
+        self.out.close().context("error while writing output")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tests::{example_output, EXAMPLE_POST_ID};
+    use parler_indexer::parse::post::RelTimestamp;
+    use parler_indexer::parse::util::{IDFromSuffix, UntrimmedString};
+
+    fn comment(id: Option<&str>, body: &str, replies: Option<Vec<Comment>>) -> Comment {
+        Comment {
+            author: None,
+            rel_timestamp: RelTimestamp {
+                rel_ts: None,
+                approx_ts_offset: None,
+                created_at: Default::default(),
+            },
+            body: Some(UntrimmedString(body.into())),
+            engagements: None,
+            media_container: None,
+            comment_id: id.map(|v| IDFromSuffix(v.into())),
+            replies,
+        }
+    }
+
+    fn bodies(comments: &[Comment]) -> Vec<String> {
+        comments
+            .iter()
+            .map(|v| v.body.as_ref().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn merges_comments() {
+        let mut newer = vec![
+            comment(Some("a"), "a new", Some(vec![comment(Some("a1"), "a1", None)])),
+            comment(None, "no id", None),
+        ];
+        let older = vec![
+            comment(Some("a"), "a old", Some(vec![comment(Some("a2"), "a2", None)])),
+            comment(Some("b"), "b", None),
+            comment(None, "no id", None),
+        ];
+        merge_comments(&mut newer, older);
+        assert_eq!(bodies(&newer), vec!["a new", "no id", "b"]);
+        assert_eq!(bodies(newer[0].replies.as_ref().unwrap()), vec!["a1", "a2"]);
+    }
+
+    fn with_counts(mut post: ParlerPost, echo_count: i64) -> ParlerPost {
+        post.engagements.as_mut().unwrap().echo_count = Some(echo_count);
+        post
+    }
+
+    fn echo_count(merged: &MergedPost) -> Option<i64> {
+        merged.post.engagements.as_ref().unwrap().echo_count
+    }
+
+    #[test]
+    fn observes_latest_copy() {
+        let post = example_output("abc").page.posts.remove(1);
+        let merged = |sha1: &str, dt, echo_count| MergedPost {
+            meta: MergeMeta {
+                post_id: EXAMPLE_POST_ID.into(),
+                post_id_synthetic: false,
+                page_sha1s: vec![sha1.into()],
+                latest_dt: dt,
+                latest_sha1: sha1.into(),
+            },
+            post: with_counts(post.clone(), echo_count),
+        };
+
+        let mut newer_first = merged("b", Some(20), 2);
+        newer_first.observe("a", Some(10), with_counts(post.clone(), 1));
+        let mut older_first = merged("a", Some(10), 1);
+        older_first.observe("b", Some(20), with_counts(post.clone(), 2));
+        for v in &[&newer_first, &older_first] {
+            assert_eq!(echo_count(v), Some(2));
+            assert_eq!(v.meta.latest_dt, Some(20));
+            assert_eq!(v.meta.latest_sha1, "b");
+        }
+        assert_eq!(newer_first.meta.page_sha1s, vec!["b", "a"]);
+        // comments of the older copy are kept
+        assert_eq!(newer_first.post.comments.len(), 1);
+
+        // same reference time, the higher sha1 wins whichever is read first
+        let mut tie = merged("b", Some(10), 2);
+        tie.observe("a", Some(10), with_counts(post.clone(), 1));
+        assert_eq!(echo_count(&tie), Some(2));
+        let mut tie = merged("a", Some(10), 1);
+        tie.observe("b", Some(10), with_counts(post.clone(), 2));
+        assert_eq!(echo_count(&tie), Some(2));
+
+        // pages without a time never win
+        let mut undated = merged("a", Some(10), 1);
+        undated.observe("b", None, with_counts(post, 2));
+        assert_eq!(echo_count(&undated), Some(1));
+        assert_eq!(undated.meta.latest_sha1, "a");
+    }
+
+    #[test]
+    fn merges_copies_without_id() {
+        let mut sink = MergeSink::new(Vec::new(), true);
+        // the feed page only has the copy without an id, it's matched to the permalink copy
+        let mut feed = example_output("def");
+        feed.page.posts.truncate(1);
+        feed.page.posts[0] = with_counts(feed.page.posts[0].clone(), 5);
+        feed.meta.reference_dt = Some(20);
+        let mut permalink = example_output("abc");
+        permalink.meta.reference_dt = Some(10);
+        sink.write(&feed).unwrap();
+        sink.write(&permalink).unwrap();
+
+        assert_eq!(sink.posts.len(), 1);
+        let merged = &sink.posts[0];
+        assert_eq!(merged.meta.post_id, EXAMPLE_POST_ID);
+        assert!(!merged.meta.post_id_synthetic);
+        assert_eq!(merged.meta.page_sha1s, vec!["def", "abc"]);
+        assert_eq!(merged.meta.latest_sha1, "def");
+        assert_eq!(echo_count(merged), Some(5));
+        assert_eq!(bodies(&merged.post.comments), vec!["first comment"]);
+
+        // a different post by someone else stays apart
+        let mut other = example_output("ghi");
+        other.page.posts.truncate(1);
+        for card in &mut other.page.posts[0].cards {
+            card.body = Some(UntrimmedString("something else".into()));
+        }
+        sink.write(&other).unwrap();
+        assert_eq!(sink.posts.len(), 2);
+        assert_eq!(sink.posts[1].meta.post_id, "ghi-0");
+        assert!(sink.posts[1].meta.post_id_synthetic);
+
+        sink.finish().unwrap();
+        assert_eq!(String::from_utf8(sink.out).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn merges_copies_scraped_at_different_times() {
+        let mut sink = MergeSink::new(Vec::new(), true);
+        // the same post in two feeds, shown as "2 days ago" and a week later as "9 days ago"
+        let feed = |sha1: &str, rel_ts: &str, dt| {
+            let mut output = example_output(sha1);
+            output.page.posts.truncate(1);
+            for card in &mut output.page.posts[0].cards {
+                card.rel_timestamp.rel_ts = Some(rel_ts.into());
+            }
+            output.meta.reference_dt = Some(dt);
+            output
+        };
+        sink.write(&feed("abc", "2 days ago", 10)).unwrap();
+        sink.write(&feed("def", "9 days ago", 20)).unwrap();
+        assert_eq!(sink.posts.len(), 1);
+        assert_eq!(sink.posts[0].meta.page_sha1s, vec!["abc", "def"]);
+        assert_eq!(sink.posts[0].meta.latest_sha1, "def");
+
+        // the same body with other media is a different post
+        let mut other = feed("ghi", "2 days ago", 10);
+        for card in &mut other.page.posts[0].cards {
+            card.media_container = None;
+        }
+        sink.write(&other).unwrap();
+        assert_eq!(sink.posts.len(), 2);
+    }
+}
@@ -0,0 +1,227 @@
+use super::Sink;
+use anyhow::{bail, Context, Result};
+use parler_indexer::ParseOutput;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Component, Path, PathBuf},
+};
+
+// One json file per input under the target dir, laid out like the inputs:
+// `<walk root>/foo/bar/page.html` becomes `<dir>/foo/bar/page.json`
+
+// Files given directly have no walk root, their path is used as given minus anything that
+// would end up outside the target dir
+fn below(path: &Path, relative: Option<&Path>) -> PathBuf {
+    match relative {
+        Some(v) => v.to_path_buf(),
+        None => path
+            .components()
+            .filter(|v| matches!(v, Component::Normal(_)))
+            .collect(),
+    }
+}
+
+/// Where the json for an html input ends up. `page` and `page.html` next to each other both
+/// end up in `page.json`, [`MirrorSink`] refuses to write the second one.
+pub fn mirror_path(dir: &Path, path: &Path, relative: Option<&Path>) -> PathBuf {
+    dir.join(below(path, relative)).with_extension("json")
+}
+
+// WARC records all share the path of their WARC file, so that becomes a directory with one
+// file per record. Stdin has no path at all, those go in the top level. Both are named after
+// the document sha1.
+fn output_path(dir: &Path, output: &ParseOutput) -> PathBuf {
+    let name = format!("{}.json", output.meta.sha1);
+    match (&output.meta.file, &output.meta.warc) {
+        (Some(file), None) => mirror_path(dir, &file.path, file.relative_path.as_deref()),
+        (Some(file), Some(_)) => dir
+            .join(below(&file.path, file.relative_path.as_deref()))
+            .join(name),
+        (None, _) => dir.join(name),
+    }
+}
+
+/// True when the json for the input is at least as new as the input, so it can be skipped
+pub fn is_up_to_date(target: &Path, input: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|v| v.modified()).ok();
+    match (modified(target), modified(input)) {
+        (Some(target), Some(input)) => target >= input,
+        _ => false,
+    }
+}
+
+pub struct MirrorSink {
+    dir: PathBuf,
+    compact: bool,
+    // the input every file was written for during this run
+    written: HashMap<PathBuf, PathBuf>,
+}
+
+impl MirrorSink {
+    pub fn new(dir: &Path, compact: bool) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            compact,
+            written: HashMap::new(),
+        }
+    }
+}
+
+impl Sink for MirrorSink {
+    fn write(&mut self, output: &ParseOutput) -> Result<()> {
+        let target = output_path(&self.dir, output);
+        let input = output.meta.file.as_ref().map_or(Path::new("-"), |v| v.path.as_path());
+        match self.written.get(&target) {
+            Some(other) if other != input => bail!(
+                "{} and {} are both mirrored to {}, rename one of them",
+                other.display(),
+                input.display(),
+                target.display()
+            ),
+            Some(_) => {}
+            None => {
+                self.written.insert(target.clone(), input.to_path_buf());
+            }
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        // an interrupted write must not leave a file that looks up to date
+        let mut tmp = target.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut out = BufWriter::new(
+            File::create(&tmp).with_context(|| format!("failed to create {}", tmp.display()))?,
+        );
+        (if self.compact {
+            serde_json::to_writer
+        } else {
+            serde_json::to_writer_pretty
+        })(&mut out, output)
+        .context("error while writing output")?;
+        writeln!(out).context("error while writing output")?;
+        out.flush().context("error while writing output")?;
+        fs::rename(&tmp, &target).with_context(|| format!("failed to write {}", target.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tests::example_output;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn mirrors_the_walked_tree() {
+        let dir = Path::new("out");
+        assert_eq!(
+            mirror_path(
+                dir,
+                Path::new("pages/foo/bar/page.html"),
+                Some(Path::new("foo/bar/page.html"))
+            ),
+            PathBuf::from("out/foo/bar/page.json")
+        );
+        assert_eq!(
+            mirror_path(
+                dir,
+                Path::new("pages/foo/page"),
+                Some(Path::new("foo/page"))
+            ),
+            PathBuf::from("out/foo/page.json")
+        );
+        assert_eq!(
+            mirror_path(dir, Path::new("/data/../pages/page.html.gz"), None),
+            PathBuf::from("out/data/pages/page.html.json")
+        );
+        assert_eq!(
+            mirror_path(
+                dir,
+                Path::new("pages/dump.tar!/a/page.html"),
+                Some(Path::new("dump.tar!/a/page.html"))
+            ),
+            PathBuf::from("out/dump.tar!/a/page.json")
+        );
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("parler-mirror-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn set_modified(path: &Path, time: SystemTime) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn skips_up_to_date_inputs() {
+        let dir = temp_dir("up-to-date");
+        let (input, target) = (dir.join("page.html"), dir.join("page.json"));
+        fs::write(&input, "html").unwrap();
+        assert!(!is_up_to_date(&target, &input));
+
+        let now = SystemTime::now();
+        fs::write(&target, "{}").unwrap();
+        set_modified(&input, now - Duration::from_secs(60));
+        set_modified(&target, now);
+        assert!(is_up_to_date(&target, &input));
+
+        // the input changed after it was mirrored
+        set_modified(&input, now + Duration::from_secs(60));
+        assert!(!is_up_to_date(&target, &input));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn writes_without_leftovers() {
+        let dir = temp_dir("write");
+        let output = example_output("abc");
+        let mut sink = MirrorSink::new(&dir, true);
+        sink.write(&output).unwrap();
+        // a second write replaces the first
+        sink.write(&output).unwrap();
+
+        let target = dir.join("examples/echo--parent-no-comment.json");
+        let files: Vec<_> = fs::read_dir(target.parent().unwrap())
+            .unwrap()
+            .map(|v| v.unwrap().path())
+            .collect();
+        assert_eq!(files, vec![target.clone()]);
+        let written: serde_json::Value =
+            serde_json::from_slice(&fs::read(&target).unwrap()).unwrap();
+        assert_eq!(written, serde_json::to_value(&output).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_inputs_with_the_same_target() {
+        let dir = temp_dir("same-target");
+        let mut sink = MirrorSink::new(&dir, true);
+        let mut output = example_output("abc");
+        sink.write(&output).unwrap();
+
+        // `echo--parent-no-comment` next to `echo--parent-no-comment.html`
+        output.meta.file.as_mut().unwrap().path.set_extension("");
+        let err = sink.write(&output).unwrap_err().to_string();
+        assert!(err.contains("are both mirrored to"), "{}", err);
+        let written: serde_json::Value = serde_json::from_slice(
+            &fs::read(dir.join("examples/echo--parent-no-comment.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            written["__meta"]["path"],
+            "examples/echo--parent-no-comment.html"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::{anyhow, Context, Result};
+use parler_indexer::{ParlerPost, ParseOutput, ScrapeMeta};
+use std::{
+    io::{self, StdoutLock, Write},
+    str::FromStr,
+};
+
+mod esbulk;
+mod merge;
+mod mirror;
+mod parquet;
+mod relational;
+mod shard;
+mod sqlite;
+
+pub use esbulk::*;
+pub use merge::*;
+pub use mirror::*;
+pub use self::parquet::*;
+pub use relational::*;
+pub use shard::*;
+pub use sqlite::*;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    EsBulk,
+    Normalized,
+    MergedPosts,
+    Mirror,
+}
+
+impl OutputFormat {
+    pub const NAMES: &'static [&'static str] = &["json", "es-bulk", "normalized", "merged-posts", "mirror"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "es-bulk" => Ok(OutputFormat::EsBulk),
+            "normalized" => Ok(OutputFormat::Normalized),
+            "merged-posts" => Ok(OutputFormat::MergedPosts),
+            "mirror" => Ok(OutputFormat::Mirror),
+            _ => Err(anyhow!("unknown output format {}", s)),
+        }
+    }
+}
+
+// Posts without an id (no comment section on the page) are keyed by their position on the page.
+// Returns the id and whether it was made up.
+pub fn post_id(meta: &ScrapeMeta, index: usize, post: &ParlerPost) -> (String, bool) {
+    match &post.post_id {
+        Some(id) => (id.as_ref().to_string(), false),
+        None => (format!("{}-{}", meta.sha1, index), true),
+    }
+}
+
+// Where parsed pages end up. Sinks live on the writer thread so they don't need to be Send.
+pub trait Sink {
+    fn write(&mut self, output: &ParseOutput) -> Result<()>;
+    // Makes everything written so far durable, the success log is only synced after this
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+    fn finish(&mut self) -> Result<()> {
+        self.flush()
+    }
+}
+
+// What the stdout formats write to. Output split into several files only starts a new one
+// between documents, so sinks report where those end.
+pub trait DocumentWrite: Write {
+    fn end_document(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    // Nothing is written after this
+    fn close(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl DocumentWrite for StdoutLock<'_> {}
+
+impl<W: DocumentWrite + ?Sized> DocumentWrite for Box<W> {
+    fn end_document(&mut self) -> io::Result<()> {
+        (**self).end_document()
+    }
+    fn close(&mut self) -> io::Result<()> {
+        (**self).close()
+    }
+}
+
+pub struct JsonSink<W> {
+    out: W,
+    compact: bool,
+}
+
+impl<W: DocumentWrite> JsonSink<W> {
+    pub fn new(out: W, compact: bool) -> Self {
+        Self { out, compact }
+    }
+}
+
+impl<W: DocumentWrite> Sink for JsonSink<W> {
+    fn write(&mut self, output: &ParseOutput) -> Result<()> {
+        (if self.compact {
+            serde_json::to_writer
+        } else {
+            serde_json::to_writer_pretty
+        })(&mut self.out, output)
+        .context("error while writing output")?;
+        writeln!(&mut self.out).context("error while writing output")?;
+        self.out.end_document().context("error while writing output")
+    }
+    fn flush(&mut self) -> Result<()> {
+        self.out.flush().context("error while writing output")
+    }
+    fn finish(&mut self) -> Result<()> {
+        self.out.close().context("error while writing output")
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use parler_indexer::parse::meta::{InputKind, OutputBuilder};
+    use parler_indexer::parse::post::RelTimestamp;
+    use parler_indexer::parse::util::{IDFromSuffix, UntrimmedString};
+    use parler_indexer::Comment;
+
+    impl DocumentWrite for Vec<u8> {}
+
+    pub const EXAMPLE_POST_ID: &str = "b9a68d4725a347b3950e13c591942c79";
+
+    pub fn comment(id: &str, body: &str) -> Comment {
+        Comment {
+            author: None,
+            rel_timestamp: RelTimestamp {
+                rel_ts: None,
+                approx_ts_offset: None,
+                created_at: Default::default(),
+            },
+            body: Some(UntrimmedString(body.into())),
+            engagements: None,
+            media_container: None,
+            comment_id: Some(IDFromSuffix(id.into())),
+            replies: None,
+        }
+    }
+
+    // The example page has a single echo without an id or comments. The second post is the
+    // same one as seen on its permalink page, with its id and a comment.
+    pub fn example_output(sha1: &str) -> ParseOutput {
+        let html = std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/examples/echo--parent-no-comment.html"
+        ))
+        .unwrap();
+        let mut page = parler_indexer::parse_page_str(&html).unwrap();
+        let mut post = parler_indexer::parse_page_str(&html).unwrap().posts.remove(0);
+        post.post_id = Some(IDFromSuffix(EXAMPLE_POST_ID.into()));
+        post.comments = vec![comment("c1", "first comment")];
+        page.posts.push(post);
+        let mut builder = OutputBuilder::new(
+            InputKind::HTML,
+            "examples/echo--parent-no-comment.html".into(),
+        );
+        builder.sha1(sha1.into());
+        builder.build(page).unwrap()
+    }
+}
@@ -0,0 +1,292 @@
+use super::{post_id, relational::Timestamp, Sink};
+use anyhow::{anyhow, Context, Result};
+use arrow_array::{ArrayRef, BooleanArray, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parler_indexer::parse::post::PostCardType;
+use parler_indexer::ParseOutput;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, GzipLevel, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use std::{fs::File, path::Path, str::FromStr, sync::Arc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ParquetCompression {
+    None,
+    #[default]
+    Snappy,
+    Gzip,
+    Zstd,
+}
+
+impl ParquetCompression {
+    pub const NAMES: &'static [&'static str] = &["none", "snappy", "gzip", "zstd"];
+
+    fn codec(&self) -> Compression {
+        match self {
+            ParquetCompression::None => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Gzip => Compression::GZIP(GzipLevel::default()),
+            ParquetCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+        }
+    }
+}
+
+impl FromStr for ParquetCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ParquetCompression::None),
+            "snappy" => Ok(ParquetCompression::Snappy),
+            "gzip" => Ok(ParquetCompression::Gzip),
+            "zstd" => Ok(ParquetCompression::Zstd),
+            _ => Err(anyhow!("unknown parquet compression {}", s)),
+        }
+    }
+}
+
+// One row per post. For echoes the author/body/timestamp come from the echoed (root) card,
+// the echoing user's own comment goes in echo_comment.
+#[derive(Debug, Default)]
+struct PostColumns {
+    page_sha1: Vec<String>,
+    source: Vec<Option<String>>,
+    post_id: Vec<String>,
+    post_id_synthetic: Vec<bool>,
+    author_username: Vec<Option<String>>,
+    author_name: Vec<Option<String>>,
+    body: Vec<Option<String>>,
+    rel_ts: Vec<Option<String>>,
+    approx_ts_offset: Vec<Option<i64>>,
+    approx_created_at: Vec<Option<String>>,
+    impression_count: Vec<Option<i64>>,
+    comment_count: Vec<Option<i64>>,
+    echo_count: Vec<Option<i64>>,
+    upvote_count: Vec<Option<i64>>,
+    is_echo: Vec<bool>,
+    echoed_by: Vec<Option<String>>,
+    echoed_rel_ts: Vec<Option<String>>,
+    echo_comment: Vec<Option<String>>,
+}
+
+fn post_schema() -> SchemaRef {
+    let utf8 = |name: &str, nullable| Field::new(name, DataType::Utf8, nullable);
+    let int64 = |name: &str| Field::new(name, DataType::Int64, true);
+    let boolean = |name: &str| Field::new(name, DataType::Boolean, false);
+    Arc::new(Schema::new(vec![
+        utf8("page_sha1", false),
+        utf8("source", true),
+        utf8("post_id", false),
+        boolean("post_id_synthetic"),
+        utf8("author_username", true),
+        utf8("author_name", true),
+        utf8("body", true),
+        utf8("rel_ts", true),
+        int64("approx_ts_offset"),
+        utf8("approx_created_at", true),
+        int64("impression_count"),
+        int64("comment_count"),
+        int64("echo_count"),
+        int64("upvote_count"),
+        boolean("is_echo"),
+        utf8("echoed_by", true),
+        utf8("echoed_rel_ts", true),
+        utf8("echo_comment", true),
+    ]))
+}
+
+impl PostColumns {
+    fn is_empty(&self) -> bool {
+        self.post_id.is_empty()
+    }
+
+    fn push(&mut self, output: &ParseOutput) {
+        for (index, post) in output.page.posts.iter().enumerate() {
+            let (id, synthetic) = post_id(&output.meta, index, post);
+            let card_of = |kind: PostCardType| post.cards.iter().find(|v| v.kind == kind);
+            let echo_parent = card_of(PostCardType::EchoParent);
+            let card = card_of(PostCardType::EchoRoot)
+                .or_else(|| card_of(PostCardType::Post))
+                .or_else(|| post.cards.first());
+            let author = card.and_then(|v| v.author.as_ref());
+            let ts = card.map(|v| Timestamp::from(&v.rel_timestamp));
+            let counts = post.engagements.as_ref();
+            let echo_by = post.echo_by.as_ref();
+
+            self.page_sha1.push(output.meta.sha1.clone());
+            self.source.push(output.meta.source.clone());
+            self.post_id.push(id);
+            self.post_id_synthetic.push(synthetic);
+            self.author_username.push(author.map(|v| v.username.clone()));
+            self.author_name.push(author.and_then(|v| v.name.clone()));
+            self.body
+                .push(card.and_then(|v| v.body.as_ref()).map(|v| v.to_string()));
+            self.rel_ts.push(ts.as_ref().and_then(|v| v.rel_ts.clone()));
+            self.approx_ts_offset
+                .push(ts.as_ref().and_then(|v| v.approx_ts_offset));
+            self.approx_created_at
+                .push(ts.and_then(|v| v.approx_created_at));
+            self.impression_count
+                .push(card.and_then(|v| v.impression_count));
+            self.comment_count.push(counts.and_then(|v| v.comment_count));
+            self.echo_count.push(counts.and_then(|v| v.echo_count));
+            self.upvote_count.push(counts.and_then(|v| v.upvote_count));
+            self.is_echo.push(echo_by.is_some() || echo_parent.is_some());
+            self.echoed_by
+                .push(echo_by.map(|v| v.name.as_ref().to_string()));
+            self.echoed_rel_ts.push(
+                echo_by
+                    .and_then(|v| v.rel_ts.as_ref())
+                    .and_then(|v| v.rel_ts.clone()),
+            );
+            self.echo_comment.push(
+                echo_parent
+                    .and_then(|v| v.body.as_ref())
+                    .map(|v| v.to_string()),
+            );
+        }
+    }
+
+    fn take_batch(&mut self, schema: &SchemaRef) -> Result<RecordBatch> {
+        let c = std::mem::take(self);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(c.page_sha1)),
+            Arc::new(StringArray::from(c.source)),
+            Arc::new(StringArray::from(c.post_id)),
+            Arc::new(BooleanArray::from(c.post_id_synthetic)),
+            Arc::new(StringArray::from(c.author_username)),
+            Arc::new(StringArray::from(c.author_name)),
+            Arc::new(StringArray::from(c.body)),
+            Arc::new(StringArray::from(c.rel_ts)),
+            Arc::new(Int64Array::from(c.approx_ts_offset)),
+            Arc::new(StringArray::from(c.approx_created_at)),
+            Arc::new(Int64Array::from(c.impression_count)),
+            Arc::new(Int64Array::from(c.comment_count)),
+            Arc::new(Int64Array::from(c.echo_count)),
+            Arc::new(Int64Array::from(c.upvote_count)),
+            Arc::new(BooleanArray::from(c.is_echo)),
+            Arc::new(StringArray::from(c.echoed_by)),
+            Arc::new(StringArray::from(c.echoed_rel_ts)),
+            Arc::new(StringArray::from(c.echo_comment)),
+        ];
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }
+}
+
+/// Writes posts to a parquet file, one row group per `pages_per_row_group` pages
+pub struct ParquetSink {
+    writer: Option<ArrowWriter<File>>,
+    schema: SchemaRef,
+    columns: PostColumns,
+    pages_per_row_group: usize,
+    pending: usize,
+}
+
+impl ParquetSink {
+    pub fn create(
+        path: &Path,
+        compression: ParquetCompression,
+        pages_per_row_group: usize,
+    ) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create parquet file {}", path.display()))?;
+        let schema = post_schema();
+        // row groups are cut by page count, don't let the writer split them on its own
+        let props = WriterProperties::builder()
+            .set_compression(compression.codec())
+            .set_max_row_group_size(usize::MAX)
+            .build();
+        let writer = ArrowWriter::try_new(file, schema.clone(), Some(props))?;
+        Ok(Self {
+            writer: Some(writer),
+            schema,
+            columns: PostColumns::default(),
+            pages_per_row_group: pages_per_row_group.max(1),
+            pending: 0,
+        })
+    }
+
+    fn flush_row_group(&mut self) -> Result<()> {
+        let writer = match &mut self.writer {
+            Some(v) => v,
+            None => return Err(anyhow!("parquet writer is already closed")),
+        };
+        if !self.columns.is_empty() {
+            let batch = self.columns.take_batch(&self.schema)?;
+            writer.write(&batch)?;
+            writer.flush()?;
+        }
+        self.pending = 0;
+        Ok(())
+    }
+}
+
+impl Sink for ParquetSink {
+    fn write(&mut self, output: &ParseOutput) -> Result<()> {
+        self.columns.push(output);
+        self.pending += 1;
+        if self.pending >= self.pages_per_row_group {
+            self.flush_row_group()
+                .context("error while writing parquet row group")?;
+        }
+        Ok(())
+    }
+    fn finish(&mut self) -> Result<()> {
+        self.flush_row_group()
+            .context("error while writing parquet row group")?;
+        if let Some(writer) = self.writer.take() {
+            writer.close().context("failed to finish parquet file")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tests::{example_output, EXAMPLE_POST_ID};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn round_trips_posts() {
+        let path = std::env::temp_dir().join(format!("parler-parquet-{}.parquet", std::process::id()));
+        let mut sink = ParquetSink::create(&path, ParquetCompression::default(), 2).unwrap();
+        for sha1 in &["abc", "def", "ghi"] {
+            sink.write(&example_output(sha1)).unwrap();
+        }
+        sink.finish().unwrap();
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 2);
+        assert_eq!(builder.schema(), &post_schema());
+        let batches = builder
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(batches.iter().map(|v| v.num_rows()).sum::<usize>(), 6);
+
+        let strings = |name: &str| -> Vec<Option<String>> {
+            batches
+                .iter()
+                .flat_map(|batch| {
+                    let column = batch.column_by_name(name).unwrap();
+                    let column = column.as_any().downcast_ref::<StringArray>().unwrap();
+                    column.iter().map(|v| v.map(str::to_string)).collect::<Vec<_>>()
+                })
+                .collect()
+        };
+        let some = |v: &str| Some(v.to_string());
+        assert_eq!(
+            strings("page_sha1"),
+            vec![some("abc"), some("abc"), some("def"), some("def"), some("ghi"), some("ghi")]
+        );
+        assert_eq!(
+            strings("post_id")[..4],
+            [some("abc-0"), some(EXAMPLE_POST_ID), some("def-0"), some(EXAMPLE_POST_ID)]
+        );
+        assert_eq!(strings("author_username")[0], some("@SidneyPowell"));
+        assert_eq!(strings("echoed_by")[0], some("Johngragg45"));
+    }
+}
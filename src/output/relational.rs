@@ -0,0 +1,530 @@
+use super::{post_id, Sink};
+use anyhow::{anyhow, Context, Result};
+use parler_indexer::parse::media::{MediaContainer, ResourceLink};
+use parler_indexer::parse::post::RelTimestamp;
+use parler_indexer::{Author, Comment, ParseOutput};
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+// One page flattened into rows for a relational database. Every row carries the page sha1,
+// posts and comments without an id on the page get a synthetic one (see `post_id`).
+
+#[derive(Debug, Default, Serialize)]
+pub struct AuthorRow {
+    pub page_sha1: String,
+    pub username: String,
+    pub name: Option<String>,
+    pub badge: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PostRow {
+    pub page_sha1: String,
+    pub post_id: String,
+    pub post_id_synthetic: bool,
+    pub post_index: usize,
+    pub echoed_by: Option<String>,
+    pub echoed_rel_ts: Option<String>,
+    pub comment_count: Option<i64>,
+    pub echo_count: Option<i64>,
+    pub upvote_count: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PostCardRow {
+    pub page_sha1: String,
+    pub post_id: String,
+    pub card_index: usize,
+    pub kind: String,
+    pub author_username: Option<String>,
+    pub rel_ts: Option<String>,
+    pub approx_ts_offset: Option<i64>,
+    pub approx_created_at: Option<String>,
+    pub body: Option<String>,
+    pub impression_count: Option<i64>,
+    pub is_sensitive_content: Option<bool>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CommentRow {
+    pub page_sha1: String,
+    pub post_id: String,
+    pub comment_id: String,
+    pub comment_id_synthetic: bool,
+    pub parent_comment_id: Option<String>,
+    pub author_username: Option<String>,
+    pub rel_ts: Option<String>,
+    pub approx_ts_offset: Option<i64>,
+    pub approx_created_at: Option<String>,
+    pub body: Option<String>,
+    pub reply_count: Option<i64>,
+    pub downvote_count: Option<i64>,
+    pub upvote_count: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MediaItemRow {
+    pub page_sha1: String,
+    pub post_id: String,
+    // set for media attached to a post card
+    pub card_index: Option<usize>,
+    // set for media attached to a comment
+    pub comment_id: Option<String>,
+    pub item_index: usize,
+    pub kind: Option<String>,
+    pub title: Option<String>,
+    pub excerpt: Option<String>,
+    pub url: Option<String>,
+    pub media_id: Option<String>,
+    pub numeric_id: Option<String>,
+    pub image_url: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MentionRow {
+    pub page_sha1: String,
+    pub post_id: String,
+    pub username: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Rows {
+    pub authors: Vec<AuthorRow>,
+    pub posts: Vec<PostRow>,
+    pub post_cards: Vec<PostCardRow>,
+    pub comments: Vec<CommentRow>,
+    pub media_items: Vec<MediaItemRow>,
+    pub mentions: Vec<MentionRow>,
+}
+
+fn link_url(link: &ResourceLink) -> String {
+    link.location
+        .as_ref()
+        .map_or_else(|| link.url_raw.clone(), |v| v.url.clone())
+}
+
+impl Rows {
+    pub fn from_output(output: &ParseOutput) -> Self {
+        let mut rows = Rows::default();
+        let mut seen_authors = HashSet::new();
+        let sha1 = output.meta.sha1.as_str();
+        let page = &output.page;
+
+        if let Some(og) = &page.opengraph_meta {
+            rows.add_author(&mut seen_authors, sha1, &og.owner.0);
+        }
+        if let Some(profile) = &page.profile {
+            rows.add_author(&mut seen_authors, sha1, &profile.user);
+        }
+        for (post_index, post) in page.posts.iter().enumerate() {
+            let (post_id, synthetic) = post_id(&output.meta, post_index, post);
+            let echo_by = post.echo_by.as_ref();
+            let counts = post.engagements.as_ref();
+            rows.posts.push(PostRow {
+                page_sha1: sha1.into(),
+                post_id: post_id.clone(),
+                post_id_synthetic: synthetic,
+                post_index,
+                echoed_by: echo_by.map(|v| v.name.as_ref().to_string()),
+                echoed_rel_ts: echo_by
+                    .and_then(|v| v.rel_ts.as_ref())
+                    .and_then(|v| v.rel_ts.clone()),
+                comment_count: counts.and_then(|v| v.comment_count),
+                echo_count: counts.and_then(|v| v.echo_count),
+                upvote_count: counts.and_then(|v| v.upvote_count),
+            });
+            for (card_index, card) in post.cards.iter().enumerate() {
+                if let Some(author) = &card.author {
+                    rows.add_author(&mut seen_authors, sha1, author);
+                }
+                let ts = Timestamp::from(&card.rel_timestamp);
+                rows.post_cards.push(PostCardRow {
+                    page_sha1: sha1.into(),
+                    post_id: post_id.clone(),
+                    card_index,
+                    kind: format!("{:?}", card.kind),
+                    author_username: card.author.as_ref().map(|v| v.username.clone()),
+                    rel_ts: ts.rel_ts,
+                    approx_ts_offset: ts.approx_ts_offset,
+                    approx_created_at: ts.approx_created_at,
+                    body: card.body.as_ref().map(|v| v.to_string()),
+                    impression_count: card.impression_count,
+                    is_sensitive_content: card
+                        .media_container
+                        .as_ref()
+                        .map(|v| v.is_sensitive_content.0),
+                });
+                rows.add_media(sha1, &post_id, Some(card_index), None, &card.media_container);
+            }
+            for (i, comment) in post.comments.iter().enumerate() {
+                let fallback = format!("{}-{}", post_id, i);
+                rows.add_comment(&mut seen_authors, sha1, &post_id, None, fallback, comment);
+            }
+            for username in post.mentions.iter().flatten() {
+                rows.mentions.push(MentionRow {
+                    page_sha1: sha1.into(),
+                    post_id: post_id.clone(),
+                    username: username.clone(),
+                });
+            }
+        }
+        rows
+    }
+
+    fn add_author(&mut self, seen: &mut HashSet<String>, sha1: &str, author: &Author) {
+        if !seen.insert(author.username.clone()) {
+            return;
+        }
+        self.authors.push(AuthorRow {
+            page_sha1: sha1.into(),
+            username: author.username.clone(),
+            name: author.name.clone(),
+            badge: author.badge.as_ref().map(|v| format!("{:?}", v.0)),
+            avatar_url: author.avatar.as_ref().map(|v| v.location.url.clone()),
+        });
+    }
+
+    fn add_comment(
+        &mut self,
+        seen_authors: &mut HashSet<String>,
+        sha1: &str,
+        post_id: &str,
+        parent: Option<&str>,
+        fallback_id: String,
+        comment: &Comment,
+    ) {
+        if let Some(author) = &comment.author {
+            self.add_author(seen_authors, sha1, author);
+        }
+        let (comment_id, synthetic) = match &comment.comment_id {
+            Some(id) => (id.as_ref().to_string(), false),
+            None => (fallback_id, true),
+        };
+        let ts = Timestamp::from(&comment.rel_timestamp);
+        let counts = comment.engagements.as_ref();
+        self.comments.push(CommentRow {
+            page_sha1: sha1.into(),
+            post_id: post_id.into(),
+            comment_id: comment_id.clone(),
+            comment_id_synthetic: synthetic,
+            parent_comment_id: parent.map(String::from),
+            author_username: comment.author.as_ref().map(|v| v.username.clone()),
+            rel_ts: ts.rel_ts,
+            approx_ts_offset: ts.approx_ts_offset,
+            approx_created_at: ts.approx_created_at,
+            body: comment.body.as_ref().map(|v| v.to_string()),
+            reply_count: counts.and_then(|v| v.reply_count),
+            downvote_count: counts.and_then(|v| v.downvote_count),
+            upvote_count: counts.and_then(|v| v.upvote_count),
+        });
+        self.add_media(sha1, post_id, None, Some(&comment_id), &comment.media_container);
+        for (i, reply) in comment.replies.iter().flatten().enumerate() {
+            let fallback = format!("{}-{}", comment_id, i);
+            self.add_comment(seen_authors, sha1, post_id, Some(&comment_id), fallback, reply);
+        }
+    }
+
+    fn add_media(
+        &mut self,
+        sha1: &str,
+        post_id: &str,
+        card_index: Option<usize>,
+        comment_id: Option<&str>,
+        container: &Option<MediaContainer>,
+    ) {
+        let items = container.iter().flat_map(|v| v.media_items.iter());
+        for (item_index, item) in items.enumerate() {
+            let source = item.source.as_ref().or(item.meta.link.as_ref());
+            self.media_items.push(MediaItemRow {
+                page_sha1: sha1.into(),
+                post_id: post_id.into(),
+                card_index,
+                comment_id: comment_id.map(String::from),
+                item_index,
+                kind: item.kind.as_ref().map(|v| format!("{:?}", v)),
+                title: item.meta.title.clone(),
+                excerpt: item.meta.excerpt.clone(),
+                url: source.map(link_url),
+                media_id: source
+                    .and_then(|v| v.id.as_ref())
+                    .and_then(|v| v.id.clone()),
+                numeric_id: item.numeric_id.as_ref().map(|v| v.as_ref().to_string()),
+                image_url: item.image.as_ref().map(|v| v.location.url.clone()),
+            });
+        }
+    }
+}
+
+pub(super) struct Timestamp {
+    pub(super) rel_ts: Option<String>,
+    pub(super) approx_ts_offset: Option<i64>,
+    pub(super) approx_created_at: Option<String>,
+}
+
+impl From<&RelTimestamp> for Timestamp {
+    fn from(ts: &RelTimestamp) -> Self {
+        Self {
+            rel_ts: ts.rel_ts.clone(),
+            approx_ts_offset: ts.approx_ts_offset.as_ref().map(|v| v.offset),
+            approx_created_at: ts.created_at.approx_created_at.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TableFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+impl TableFormat {
+    pub const NAMES: &'static [&'static str] = &["csv", "ndjson"];
+
+    fn extension(&self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            TableFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+impl FromStr for TableFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(TableFormat::Csv),
+            "ndjson" => Ok(TableFormat::Ndjson),
+            _ => Err(anyhow!("unknown table format {}", s)),
+        }
+    }
+}
+
+enum TableWriter {
+    Csv(Box<csv::Writer<BufWriter<File>>>),
+    Ndjson(BufWriter<File>),
+}
+
+// csv only writes the header along with the first row, so a table without rows would come
+// out as an empty file. Serializing a blank row gets the header without writing that row.
+fn csv_header<T: Serialize + Default>() -> Result<csv::StringRecord> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.serialize(T::default())?;
+    let data = writer.into_inner()?;
+    Ok(csv::Reader::from_reader(data.as_slice()).headers()?.clone())
+}
+
+impl TableWriter {
+    // `T` is the row type of the table, csv tables get its header right away
+    fn create<T: Serialize + Default>(
+        dir: &Path,
+        table: &str,
+        format: TableFormat,
+    ) -> Result<Self> {
+        let path = dir.join(format!("{}.{}", table, format.extension()));
+        let file = File::create(&path)
+            .with_context(|| format!("failed to create table file {}", path.display()))?;
+        let out = BufWriter::new(file);
+        Ok(match format {
+            TableFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(out);
+                writer
+                    .write_record(&csv_header::<T>()?)
+                    .with_context(|| format!("failed to write table file {}", path.display()))?;
+                TableWriter::Csv(Box::new(writer))
+            }
+            TableFormat::Ndjson => TableWriter::Ndjson(out),
+        })
+    }
+
+    fn write_all<T: Serialize>(&mut self, rows: &[T]) -> Result<()> {
+        for row in rows {
+            match self {
+                TableWriter::Csv(w) => w.serialize(row)?,
+                TableWriter::Ndjson(w) => {
+                    serde_json::to_writer(&mut *w, row)?;
+                    writeln!(w)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            TableWriter::Csv(w) => w.flush(),
+            TableWriter::Ndjson(w) => w.flush(),
+        }
+        .context("error while writing table output")
+    }
+}
+
+pub struct RelationalSink {
+    authors: TableWriter,
+    posts: TableWriter,
+    post_cards: TableWriter,
+    comments: TableWriter,
+    media_items: TableWriter,
+    mentions: TableWriter,
+}
+
+impl RelationalSink {
+    pub fn create(dir: &Path, format: TableFormat) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create table directory {}", dir.display()))?;
+        Ok(Self {
+            authors: TableWriter::create::<AuthorRow>(dir, "authors", format)?,
+            posts: TableWriter::create::<PostRow>(dir, "posts", format)?,
+            post_cards: TableWriter::create::<PostCardRow>(dir, "post_cards", format)?,
+            comments: TableWriter::create::<CommentRow>(dir, "comments", format)?,
+            media_items: TableWriter::create::<MediaItemRow>(dir, "media_items", format)?,
+            mentions: TableWriter::create::<MentionRow>(dir, "mentions", format)?,
+        })
+    }
+}
+
+impl Sink for RelationalSink {
+    fn write(&mut self, output: &ParseOutput) -> Result<()> {
+        let rows = Rows::from_output(output);
+        (|| -> Result<()> {
+            self.authors.write_all(&rows.authors)?;
+            self.posts.write_all(&rows.posts)?;
+            self.post_cards.write_all(&rows.post_cards)?;
+            self.comments.write_all(&rows.comments)?;
+            self.media_items.write_all(&rows.media_items)?;
+            self.mentions.write_all(&rows.mentions)
+        })()
+        .context("error while writing table output")
+    }
+    fn flush(&mut self) -> Result<()> {
+        for table in [
+            &mut self.authors,
+            &mut self.posts,
+            &mut self.post_cards,
+            &mut self.comments,
+            &mut self.media_items,
+            &mut self.mentions,
+        ] {
+            table.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tests::{comment, example_output, EXAMPLE_POST_ID};
+
+    #[test]
+    fn flattens_a_page() {
+        let mut output = example_output("abc");
+        let mut reply = comment("r1", "reply");
+        reply.comment_id = None;
+        let mut parent = comment("c2", "no id");
+        parent.comment_id = None;
+        parent.replies = Some(vec![reply]);
+        output.page.posts[1].comments.push(parent);
+        let rows = Rows::from_output(&output);
+
+        let authors: Vec<_> = rows.authors.iter().map(|v| v.username.as_str()).collect();
+        assert_eq!(authors, vec!["@Johngragg45", "@SidneyPowell"]);
+
+        let posts: Vec<_> = rows
+            .posts
+            .iter()
+            .map(|v| (v.post_id.as_str(), v.post_id_synthetic, v.post_index))
+            .collect();
+        assert_eq!(posts, vec![("abc-0", true, 0), (EXAMPLE_POST_ID, false, 1)]);
+        assert_eq!(rows.posts[0].echoed_by.as_deref(), Some("Johngragg45"));
+
+        let cards: Vec<_> = rows.post_cards.iter().map(|v| v.post_id.as_str()).collect();
+        assert_eq!(cards, vec!["abc-0", EXAMPLE_POST_ID]);
+        assert_eq!(rows.post_cards[0].kind, "EchoParent");
+        assert_eq!(
+            rows.post_cards[0].author_username.as_deref(),
+            Some("@SidneyPowell")
+        );
+
+        assert_eq!(rows.media_items.len(), 4);
+        assert!(rows
+            .media_items
+            .iter()
+            .all(|v| v.card_index == Some(0) && v.comment_id.is_none()));
+        assert_eq!(rows.media_items[3].post_id, EXAMPLE_POST_ID);
+        assert_eq!(rows.media_items[3].item_index, 1);
+
+        // comments without an id are keyed by their position below the post or comment
+        let fallback = format!("{}-1", EXAMPLE_POST_ID);
+        let reply_fallback = format!("{}-0", fallback);
+        let comments: Vec<_> = rows
+            .comments
+            .iter()
+            .map(|v| {
+                (
+                    v.comment_id.as_str(),
+                    v.comment_id_synthetic,
+                    v.parent_comment_id.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            comments,
+            vec![
+                ("c1", false, None),
+                (fallback.as_str(), true, None),
+                (reply_fallback.as_str(), true, Some(fallback.as_str())),
+            ]
+        );
+        assert!(rows.comments.iter().all(|v| v.post_id == EXAMPLE_POST_ID));
+        assert!(rows.mentions.is_empty());
+        assert!(rows.posts.iter().all(|v| v.page_sha1 == "abc"));
+    }
+
+    #[test]
+    fn writes_csv_tables() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("parler-tables-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut sink = RelationalSink::create(&dir, TableFormat::Csv)?;
+        sink.write(&example_output("abc"))?;
+        sink.finish()?;
+
+        let read = |table: &str| -> Result<Vec<Vec<String>>> {
+            let mut reader = csv::Reader::from_path(dir.join(format!("{}.csv", table)))?;
+            let mut rows = vec![reader.headers()?.iter().map(String::from).collect()];
+            for record in reader.records() {
+                rows.push(record?.iter().map(String::from).collect());
+            }
+            Ok(rows)
+        };
+        let posts = read("posts")?;
+        assert_eq!(posts.len(), 3);
+        assert_eq!(posts[0][..3], ["page_sha1", "post_id", "post_id_synthetic"]);
+        assert_eq!(posts[1][..3], ["abc", "abc-0", "true"]);
+        assert_eq!(posts[2][..3], ["abc", EXAMPLE_POST_ID, "false"]);
+
+        let comments = read("comments")?;
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0][9], "body");
+        assert_eq!(comments[1][1..3], [EXAMPLE_POST_ID, "c1"]);
+        assert_eq!(comments[1][9], "first comment");
+        assert_eq!(read("media_items")?.len(), 5);
+        // tables without rows still get their header
+        assert_eq!(
+            read("mentions")?,
+            vec![vec!["page_sha1", "post_id", "username"]]
+        );
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
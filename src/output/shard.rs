@@ -0,0 +1,348 @@
+use super::DocumentWrite;
+use anyhow::{anyhow, Context, Result};
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ShardCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl ShardCompression {
+    pub const NAMES: &'static [&'static str] = &["none", "gzip", "zstd"];
+
+    fn extension(self) -> &'static str {
+        match self {
+            ShardCompression::None => "",
+            ShardCompression::Gzip => ".gz",
+            ShardCompression::Zstd => ".zst",
+        }
+    }
+}
+
+impl FromStr for ShardCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ShardCompression::None),
+            "gzip" => Ok(ShardCompression::Gzip),
+            "zstd" => Ok(ShardCompression::Zstd),
+            _ => Err(anyhow!("unknown shard compression {}", s)),
+        }
+    }
+}
+
+/// When to start a new shard, whichever is reached first. Without either everything goes
+/// into one shard.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ShardLimits {
+    pub documents: Option<u64>,
+    /// Counted before compression
+    pub bytes: Option<u64>,
+}
+
+/// One entry of the manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShardInfo {
+    pub name: String,
+    pub documents: u64,
+    /// Size of the file, after compression
+    pub bytes: u64,
+    /// sha1 of the file
+    pub sha1: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    shards: Vec<ShardInfo>,
+}
+
+// Hashes and counts the bytes that end up in the file
+struct Checksummed {
+    out: BufWriter<File>,
+    hasher: Sha1,
+    bytes: u64,
+}
+
+impl Write for Checksummed {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.bytes += n as u64;
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+enum Encoder {
+    Plain(Checksummed),
+    Gzip(GzEncoder<Checksummed>),
+    Zstd(zstd::Encoder<'static, Checksummed>),
+}
+
+impl Encoder {
+    fn finish(self) -> io::Result<Checksummed> {
+        match self {
+            Encoder::Plain(v) => Ok(v),
+            Encoder::Gzip(v) => v.finish(),
+            Encoder::Zstd(v) => v.finish(),
+        }
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(v) => v.write(buf),
+            Encoder::Gzip(v) => v.write(buf),
+            Encoder::Zstd(v) => v.write(buf),
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(v) => v.flush(),
+            Encoder::Gzip(v) => v.flush(),
+            Encoder::Zstd(v) => v.flush(),
+        }
+    }
+}
+
+struct Shard {
+    name: String,
+    out: Encoder,
+    documents: u64,
+    bytes: u64,
+}
+
+/// Splits the output into `part-00000.<extension>`, `part-00001.<extension>`, ... and keeps a
+/// `manifest.json` of the finished shards next to them
+pub struct ShardWriter {
+    dir: PathBuf,
+    extension: String,
+    compression: ShardCompression,
+    limits: ShardLimits,
+    next_index: usize,
+    current: Option<Shard>,
+    manifest: Manifest,
+}
+
+impl ShardWriter {
+    /// Creates `dir` if needed. When it already has shards from an earlier run, numbering
+    /// continues after them and they stay in the manifest.
+    pub fn create(
+        dir: &Path,
+        extension: &str,
+        compression: ShardCompression,
+        limits: ShardLimits,
+    ) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create output dir {}", dir.display()))?;
+        let manifest_path = dir.join(MANIFEST);
+        let manifest = if manifest_path.exists() {
+            serde_json::from_slice(&fs::read(&manifest_path)?)
+                .with_context(|| format!("failed to read {}", manifest_path.display()))?
+        } else {
+            Manifest::default()
+        };
+        // shards left behind by an interrupted run aren't in the manifest, they still
+        // mustn't be overwritten
+        let mut next_index = 0;
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let index = name
+                .to_str()
+                .and_then(|v| v.strip_prefix("part-"))
+                .and_then(|v| v.split('.').next())
+                .and_then(|v| v.parse::<usize>().ok());
+            if let Some(index) = index {
+                next_index = next_index.max(index + 1);
+            }
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            extension: format!("{}{}", extension, compression.extension()),
+            compression,
+            limits,
+            next_index,
+            current: None,
+            manifest,
+        })
+    }
+
+    fn open_shard(&mut self) -> io::Result<&mut Shard> {
+        if self.current.is_none() {
+            let name = format!("part-{:05}.{}", self.next_index, self.extension);
+            let file = Checksummed {
+                out: BufWriter::new(File::create(self.dir.join(&name))?),
+                hasher: Sha1::new(),
+                bytes: 0,
+            };
+            let out = match self.compression {
+                ShardCompression::None => Encoder::Plain(file),
+                ShardCompression::Gzip => Encoder::Gzip(GzEncoder::new(file, Compression::default())),
+                ShardCompression::Zstd => Encoder::Zstd(zstd::Encoder::new(file, 0)?),
+            };
+            self.next_index += 1;
+            self.current = Some(Shard {
+                name,
+                out,
+                documents: 0,
+                bytes: 0,
+            });
+        }
+        Ok(self.current.as_mut().unwrap())
+    }
+
+    fn close_shard(&mut self) -> io::Result<()> {
+        if let Some(shard) = self.current.take() {
+            let mut file = shard.out.finish()?;
+            file.flush()?;
+            self.manifest.shards.push(ShardInfo {
+                name: shard.name,
+                documents: shard.documents,
+                bytes: file.bytes,
+                sha1: format!("{:x}", file.hasher.finalize()),
+            });
+            self.write_manifest()?;
+        }
+        Ok(())
+    }
+
+    // replaced in one go, a crash never leaves a half written manifest
+    fn write_manifest(&self) -> io::Result<()> {
+        let tmp = self.dir.join(format!("{}.tmp", MANIFEST));
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer_pretty(&mut out, &self.manifest)?;
+        writeln!(out)?;
+        out.flush()?;
+        fs::rename(tmp, self.dir.join(MANIFEST))
+    }
+}
+
+impl Write for ShardWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let shard = self.open_shard()?;
+        let n = shard.out.write(buf)?;
+        shard.bytes += n as u64;
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.current {
+            Some(shard) => shard.out.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl DocumentWrite for ShardWriter {
+    fn end_document(&mut self) -> io::Result<()> {
+        let limits = self.limits;
+        let full = match &mut self.current {
+            Some(shard) => {
+                shard.documents += 1;
+                limits.documents.is_some_and(|v| shard.documents >= v)
+                    || limits.bytes.is_some_and(|v| shard.bytes >= v)
+            }
+            None => false,
+        };
+        if full {
+            self.close_shard()?;
+        }
+        Ok(())
+    }
+    fn close(&mut self) -> io::Result<()> {
+        self.close_shard()?;
+        self.write_manifest()
+    }
+}
+
+/// Parses sizes like `500000`, `64K`, `512M` or `2G` (powers of 1024)
+pub fn parse_size(v: &str) -> Result<u64, String> {
+    let (digits, unit) = match v.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => v.split_at(i),
+        None => (v, ""),
+    };
+    let multiplier = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return Err(format!("invalid size {:?}", v)),
+    };
+    match digits.parse::<u64>() {
+        Ok(n) if n > 0 => n
+            .checked_mul(multiplier)
+            .ok_or_else(|| format!("size {:?} is too large", v)),
+        _ => Err(format!("invalid size {:?}", v)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1000"), Ok(1000));
+        assert_eq!(parse_size("64k"), Ok(64 << 10));
+        assert_eq!(parse_size("512M"), Ok(512 << 20));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("12 parsecs").is_err());
+    }
+
+    #[test]
+    fn rotates_shards() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("parler-shards-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let limits = ShardLimits {
+            documents: Some(2),
+            bytes: None,
+        };
+        let mut out = ShardWriter::create(&dir, "ndjson", ShardCompression::Gzip, limits)?;
+        for i in 0..5 {
+            writeln!(out, "{{\"doc\":{}}}", i)?;
+            out.end_document()?;
+        }
+        out.close()?;
+
+        let manifest: Manifest = serde_json::from_slice(&fs::read(dir.join(MANIFEST))?)?;
+        let names: Vec<_> = manifest.shards.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["part-00000.ndjson.gz", "part-00001.ndjson.gz", "part-00002.ndjson.gz"]
+        );
+        assert_eq!(manifest.shards[2].documents, 1);
+        let compressed = fs::read(dir.join("part-00001.ndjson.gz"))?;
+        assert_eq!(manifest.shards[1].bytes, compressed.len() as u64);
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice()).read_to_string(&mut text)?;
+        assert_eq!(text, "{\"doc\":2}\n{\"doc\":3}\n");
+
+        // a second run adds to the manifest instead of overwriting the shards
+        let mut out = ShardWriter::create(&dir, "ndjson", ShardCompression::Gzip, limits)?;
+        writeln!(out, "{{\"doc\":5}}")?;
+        out.end_document()?;
+        out.close()?;
+        let manifest: Manifest = serde_json::from_slice(&fs::read(dir.join(MANIFEST))?)?;
+        assert_eq!(manifest.shards.len(), 4);
+        assert_eq!(manifest.shards[3].name, "part-00003.ndjson.gz");
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
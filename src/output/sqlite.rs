@@ -0,0 +1,399 @@
+use super::{Rows, Sink};
+use anyhow::{Context, Result};
+use parler_indexer::ParseOutput;
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+// pages/posts/comments are upserted so the same database can be fed from several runs.
+// FTS5 tables use the card/comment tables as external content and are kept in sync by triggers.
+const SCHEMA: &str = "
+PRAGMA journal_mode = WAL;
+PRAGMA synchronous = NORMAL;
+
+CREATE TABLE IF NOT EXISTS pages (
+    sha1 TEXT PRIMARY KEY,
+    path TEXT,
+    source TEXT,
+    create_dt INTEGER,
+    modified_dt INTEGER,
+    parse_dt INTEGER,
+    reference_dt INTEGER,
+    warc_target_uri TEXT,
+    warc_date TEXT,
+    warc_record_id TEXT,
+    title TEXT,
+    url TEXT,
+    owner_username TEXT
+);
+
+CREATE TABLE IF NOT EXISTS authors (
+    username TEXT PRIMARY KEY,
+    page_sha1 TEXT NOT NULL,
+    name TEXT,
+    badge TEXT,
+    avatar_url TEXT
+);
+
+CREATE TABLE IF NOT EXISTS posts (
+    post_id TEXT PRIMARY KEY,
+    page_sha1 TEXT NOT NULL REFERENCES pages(sha1),
+    post_id_synthetic INTEGER NOT NULL,
+    echoed_by TEXT,
+    echoed_rel_ts TEXT,
+    comment_count INTEGER,
+    echo_count INTEGER,
+    upvote_count INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS post_cards (
+    post_id TEXT NOT NULL REFERENCES posts(post_id),
+    card_index INTEGER NOT NULL,
+    page_sha1 TEXT NOT NULL REFERENCES pages(sha1),
+    kind TEXT NOT NULL,
+    author_username TEXT,
+    rel_ts TEXT,
+    approx_ts_offset INTEGER,
+    approx_created_at TEXT,
+    body TEXT,
+    impression_count INTEGER,
+    is_sensitive_content INTEGER,
+    PRIMARY KEY (post_id, card_index)
+);
+
+CREATE TABLE IF NOT EXISTS comments (
+    comment_id TEXT PRIMARY KEY,
+    post_id TEXT NOT NULL REFERENCES posts(post_id),
+    page_sha1 TEXT NOT NULL REFERENCES pages(sha1),
+    comment_id_synthetic INTEGER NOT NULL,
+    parent_comment_id TEXT,
+    author_username TEXT,
+    rel_ts TEXT,
+    approx_ts_offset INTEGER,
+    approx_created_at TEXT,
+    body TEXT,
+    reply_count INTEGER,
+    downvote_count INTEGER,
+    upvote_count INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS media_items (
+    post_id TEXT NOT NULL REFERENCES posts(post_id),
+    page_sha1 TEXT NOT NULL REFERENCES pages(sha1),
+    card_index INTEGER,
+    comment_id TEXT,
+    item_index INTEGER NOT NULL,
+    kind TEXT,
+    title TEXT,
+    excerpt TEXT,
+    url TEXT,
+    media_id TEXT,
+    numeric_id TEXT,
+    image_url TEXT
+);
+CREATE INDEX IF NOT EXISTS media_items_post_id ON media_items(post_id);
+
+CREATE TABLE IF NOT EXISTS mentions (
+    post_id TEXT NOT NULL REFERENCES posts(post_id),
+    username TEXT NOT NULL,
+    page_sha1 TEXT NOT NULL REFERENCES pages(sha1),
+    PRIMARY KEY (post_id, username)
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS post_cards_fts USING fts5(
+    body, content = 'post_cards', content_rowid = 'rowid'
+);
+CREATE TRIGGER IF NOT EXISTS post_cards_fts_insert AFTER INSERT ON post_cards BEGIN
+    INSERT INTO post_cards_fts(rowid, body) VALUES (new.rowid, new.body);
+END;
+CREATE TRIGGER IF NOT EXISTS post_cards_fts_delete AFTER DELETE ON post_cards BEGIN
+    INSERT INTO post_cards_fts(post_cards_fts, rowid, body) VALUES ('delete', old.rowid, old.body);
+END;
+CREATE TRIGGER IF NOT EXISTS post_cards_fts_update AFTER UPDATE ON post_cards BEGIN
+    INSERT INTO post_cards_fts(post_cards_fts, rowid, body) VALUES ('delete', old.rowid, old.body);
+    INSERT INTO post_cards_fts(rowid, body) VALUES (new.rowid, new.body);
+END;
+
+CREATE VIRTUAL TABLE IF NOT EXISTS comments_fts USING fts5(
+    body, content = 'comments', content_rowid = 'rowid'
+);
+CREATE TRIGGER IF NOT EXISTS comments_fts_insert AFTER INSERT ON comments BEGIN
+    INSERT INTO comments_fts(rowid, body) VALUES (new.rowid, new.body);
+END;
+CREATE TRIGGER IF NOT EXISTS comments_fts_delete AFTER DELETE ON comments BEGIN
+    INSERT INTO comments_fts(comments_fts, rowid, body) VALUES ('delete', old.rowid, old.body);
+END;
+CREATE TRIGGER IF NOT EXISTS comments_fts_update AFTER UPDATE ON comments BEGIN
+    INSERT INTO comments_fts(comments_fts, rowid, body) VALUES ('delete', old.rowid, old.body);
+    INSERT INTO comments_fts(rowid, body) VALUES (new.rowid, new.body);
+END;
+";
+
+// one transaction per batch of pages, a transaction per page is painfully slow
+const PAGES_PER_TRANSACTION: usize = 500;
+
+pub struct SqliteSink {
+    conn: Connection,
+    pending: usize,
+}
+
+impl SqliteSink {
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("failed to open sqlite database {}", path.display()))?;
+        conn.execute_batch(SCHEMA)
+            .context("failed to create sqlite schema")?;
+        conn.execute_batch("BEGIN")?;
+        Ok(Self { conn, pending: 0 })
+    }
+
+    fn upsert(&mut self, output: &ParseOutput) -> rusqlite::Result<()> {
+        let meta = &output.meta;
+        let og = output.page.opengraph_meta.as_ref();
+        let file = meta.file.as_ref();
+        let warc = meta.warc.as_ref();
+        self.conn
+            .prepare_cached(
+                "INSERT INTO pages (sha1, path, source, create_dt, modified_dt, parse_dt, reference_dt,
+                    warc_target_uri, warc_date, warc_record_id, title, url, owner_username)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+                ON CONFLICT (sha1) DO UPDATE SET
+                    path = excluded.path, source = excluded.source, create_dt = excluded.create_dt,
+                    modified_dt = excluded.modified_dt, parse_dt = excluded.parse_dt,
+                    reference_dt = excluded.reference_dt, warc_target_uri = excluded.warc_target_uri,
+                    warc_date = excluded.warc_date, warc_record_id = excluded.warc_record_id,
+                    title = excluded.title, url = excluded.url, owner_username = excluded.owner_username",
+            )?
+            .execute(params![
+                meta.sha1,
+                file.map(|v| v.path.to_string_lossy().into_owned()),
+                meta.source,
+                file.and_then(|v| v.create_dt).map(|v| v as i64),
+                file.and_then(|v| v.modified_dt).map(|v| v as i64),
+                meta.parse_dt.map(|v| v as i64),
+                meta.reference_dt.map(|v| v as i64),
+                warc.and_then(|v| v.target_uri.as_ref()),
+                warc.and_then(|v| v.date.as_ref()),
+                warc.and_then(|v| v.record_id.as_ref()),
+                og.map(|v| &v.title),
+                og.map(|v| &v.url),
+                og.map(|v| &v.owner.0.username),
+            ])?;
+
+        let rows = Rows::from_output(output);
+        for row in &rows.authors {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO authors (username, page_sha1, name, badge, avatar_url)
+                    VALUES (?1, ?2, ?3, ?4, ?5)
+                    ON CONFLICT (username) DO UPDATE SET
+                        page_sha1 = excluded.page_sha1,
+                        name = coalesce(excluded.name, name),
+                        badge = coalesce(excluded.badge, badge),
+                        avatar_url = coalesce(excluded.avatar_url, avatar_url)",
+                )?
+                .execute(params![row.username, row.page_sha1, row.name, row.badge, row.avatar_url])?;
+        }
+        for row in &rows.posts {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO posts (post_id, page_sha1, post_id_synthetic, echoed_by, echoed_rel_ts,
+                        comment_count, echo_count, upvote_count)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                    ON CONFLICT (post_id) DO UPDATE SET
+                        page_sha1 = excluded.page_sha1, post_id_synthetic = excluded.post_id_synthetic,
+                        echoed_by = excluded.echoed_by, echoed_rel_ts = excluded.echoed_rel_ts,
+                        comment_count = excluded.comment_count, echo_count = excluded.echo_count,
+                        upvote_count = excluded.upvote_count",
+                )?
+                .execute(params![
+                    row.post_id,
+                    row.page_sha1,
+                    row.post_id_synthetic,
+                    row.echoed_by,
+                    row.echoed_rel_ts,
+                    row.comment_count,
+                    row.echo_count,
+                    row.upvote_count,
+                ])?;
+            // media and mentions have no key of their own, replace them with the latest copy.
+            // Cards are keyed by position, drop the ones past the end of the latest copy.
+            let cards = rows
+                .post_cards
+                .iter()
+                .filter(|v| v.post_id == row.post_id)
+                .count();
+            self.conn
+                .prepare_cached("DELETE FROM post_cards WHERE post_id = ?1 AND card_index >= ?2")?
+                .execute(params![row.post_id, cards as i64])?;
+            self.conn
+                .prepare_cached("DELETE FROM media_items WHERE post_id = ?1")?
+                .execute(params![row.post_id])?;
+            self.conn
+                .prepare_cached("DELETE FROM mentions WHERE post_id = ?1")?
+                .execute(params![row.post_id])?;
+        }
+        for row in &rows.post_cards {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO post_cards (post_id, card_index, page_sha1, kind, author_username, rel_ts,
+                        approx_ts_offset, approx_created_at, body, impression_count, is_sensitive_content)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                    ON CONFLICT (post_id, card_index) DO UPDATE SET
+                        page_sha1 = excluded.page_sha1, kind = excluded.kind,
+                        author_username = excluded.author_username, rel_ts = excluded.rel_ts,
+                        approx_ts_offset = excluded.approx_ts_offset,
+                        approx_created_at = excluded.approx_created_at, body = excluded.body,
+                        impression_count = excluded.impression_count,
+                        is_sensitive_content = excluded.is_sensitive_content",
+                )?
+                .execute(params![
+                    row.post_id,
+                    row.card_index as i64,
+                    row.page_sha1,
+                    row.kind,
+                    row.author_username,
+                    row.rel_ts,
+                    row.approx_ts_offset,
+                    row.approx_created_at,
+                    row.body,
+                    row.impression_count,
+                    row.is_sensitive_content,
+                ])?;
+        }
+        for row in &rows.comments {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO comments (comment_id, post_id, page_sha1, comment_id_synthetic,
+                        parent_comment_id, author_username, rel_ts, approx_ts_offset, approx_created_at,
+                        body, reply_count, downvote_count, upvote_count)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+                    ON CONFLICT (comment_id) DO UPDATE SET
+                        post_id = excluded.post_id, page_sha1 = excluded.page_sha1,
+                        comment_id_synthetic = excluded.comment_id_synthetic,
+                        parent_comment_id = excluded.parent_comment_id,
+                        author_username = excluded.author_username, rel_ts = excluded.rel_ts,
+                        approx_ts_offset = excluded.approx_ts_offset,
+                        approx_created_at = excluded.approx_created_at, body = excluded.body,
+                        reply_count = excluded.reply_count, downvote_count = excluded.downvote_count,
+                        upvote_count = excluded.upvote_count",
+                )?
+                .execute(params![
+                    row.comment_id,
+                    row.post_id,
+                    row.page_sha1,
+                    row.comment_id_synthetic,
+                    row.parent_comment_id,
+                    row.author_username,
+                    row.rel_ts,
+                    row.approx_ts_offset,
+                    row.approx_created_at,
+                    row.body,
+                    row.reply_count,
+                    row.downvote_count,
+                    row.upvote_count,
+                ])?;
+        }
+        for row in &rows.media_items {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO media_items (post_id, page_sha1, card_index, comment_id, item_index, kind,
+                        title, excerpt, url, media_id, numeric_id, image_url)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                )?
+                .execute(params![
+                    row.post_id,
+                    row.page_sha1,
+                    row.card_index.map(|v| v as i64),
+                    row.comment_id,
+                    row.item_index as i64,
+                    row.kind,
+                    row.title,
+                    row.excerpt,
+                    row.url,
+                    row.media_id,
+                    row.numeric_id,
+                    row.image_url,
+                ])?;
+        }
+        for row in &rows.mentions {
+            self.conn
+                .prepare_cached(
+                    "INSERT OR IGNORE INTO mentions (post_id, username, page_sha1) VALUES (?1, ?2, ?3)",
+                )?
+                .execute(params![row.post_id, row.username, row.page_sha1])?;
+        }
+        Ok(())
+    }
+}
+
+impl Sink for SqliteSink {
+    fn write(&mut self, output: &ParseOutput) -> Result<()> {
+        self.upsert(output)
+            .with_context(|| format!("failed to write {} to sqlite", output.meta.sha1))?;
+        self.pending += 1;
+        if self.pending >= PAGES_PER_TRANSACTION {
+            self.conn.execute_batch("COMMIT; BEGIN")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+    fn flush(&mut self) -> Result<()> {
+        self.conn.execute_batch("COMMIT; BEGIN")?;
+        self.pending = 0;
+        Ok(())
+    }
+    fn finish(&mut self) -> Result<()> {
+        self.conn
+            .execute_batch("COMMIT")
+            .context("failed to commit sqlite transaction")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tests::example_output;
+    use parler_indexer::parse::util::UntrimmedString;
+
+    fn count(sink: &SqliteSink, sql: &str) -> i64 {
+        sink.conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn upserts_and_searches() -> Result<()> {
+        let mut sink = SqliteSink::open(Path::new(":memory:"))?;
+        let mut output = example_output("abc");
+        let mut card = example_output("abc").page.posts.remove(1).cards.remove(0);
+        card.body = Some(UntrimmedString("a second card about zebras".into()));
+        output.page.posts[1].cards.push(card);
+        sink.write(&output)?;
+        assert_eq!(count(&sink, "SELECT count(*) FROM post_cards"), 3);
+        let zebras = "SELECT count(*) FROM post_cards_fts WHERE post_cards_fts MATCH 'zebras'";
+        assert_eq!(count(&sink, zebras), 1);
+
+        // the same page parsed again, now with one card less
+        sink.write(&example_output("abc"))?;
+        sink.finish()?;
+        assert_eq!(count(&sink, "SELECT count(*) FROM pages"), 1);
+        assert_eq!(count(&sink, "SELECT count(*) FROM posts"), 2);
+        assert_eq!(count(&sink, "SELECT count(*) FROM post_cards"), 2);
+        assert_eq!(count(&sink, "SELECT count(*) FROM comments"), 1);
+        assert_eq!(count(&sink, "SELECT count(*) FROM media_items"), 4);
+        assert_eq!(count(&sink, zebras), 0);
+        assert_eq!(
+            count(
+                &sink,
+                "SELECT count(*) FROM post_cards_fts WHERE post_cards_fts MATCH 'capitol'"
+            ),
+            2
+        );
+        assert_eq!(
+            count(
+                &sink,
+                "SELECT count(*) FROM comments_fts WHERE comments_fts MATCH 'first'"
+            ),
+            1
+        );
+        Ok(())
+    }
+}
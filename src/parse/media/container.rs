@@ -1,11 +1,12 @@
 use super::prelude::*;
 use super::MediaItem;
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[html(selector = "div.media-container--wrapper")]
 pub struct MediaContainer {
     #[html(selector = ".sensitive--content--wrapper")]
-    is_sensitive_content: ElementExists,
+    /// Whether parler hid the media behind a sensitive content warning
+    pub is_sensitive_content: ElementExists,
     #[html(selector = "div.mc-video--container,
     div.mc-image--container,
     div.mc-basic--container,
@@ -13,5 +14,6 @@ pub struct MediaContainer {
     div.mc-website--container,
     div.mc-iframe-embed--container,
     div.mc-audio--container")]
-    media_items: Vec<MediaItem>,
+    /// Every attachment in the container, in page order
+    pub media_items: Vec<MediaItem>,
 }
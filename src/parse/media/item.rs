@@ -2,25 +2,30 @@ use super::super::util::ShouldSkip;
 use super::{prelude::*, ResourceLink};
 use super::{MediaKind, MediaMetadata};
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct MediaItem {
-    kind: Option<MediaKind>,
+    /// Video, image, article... taken from the container class
+    pub kind: Option<MediaKind>,
     #[serde(flatten)]
-    meta: MediaMetadata,
+    /// Title, link and excerpt shown next to the media
+    pub meta: MediaMetadata,
     #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
     #[html(selector = "div.mc-website--image,
     div.mc-article--image,
     div.mc-video--image,
     div.mc-audio--image
     ")]
-    image: Option<super::SimpleImage>,
+    /// Preview image for videos, articles and websites
+    pub image: Option<super::SimpleImage>,
     #[html(selector = "div.mc-video--wrapper,
         div.mc-image--wrapper,
         div.mc-article--wrapper,
         div.mc-iframe-embed--wrapper,
         div.mc-audio--wrapper,
         div.mc-website--wrapper")]
-    source: Option<ResourceLink>,
+    /// The media itself (video file, image, embedded iframe...)
+    pub source: Option<ResourceLink>,
     #[html(selector = "div.mc-image--modal", attr = "id")]
-    numeric_id: Option<IDFromSuffix>,
+    /// Numeric id of images, taken from the id of their modal
+    pub numeric_id: Option<IDFromSuffix>,
 }
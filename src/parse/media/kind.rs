@@ -17,7 +17,7 @@ macro_rules! match_class {
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum MediaKind {
     Video,
     Audio,
@@ -1,7 +1,7 @@
 use super::prelude::*;
 use super::ResourceLink;
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[html(selector = "div.mc-article--meta--wrapper,
 div.mc-basic--meta--wrapper,
 div.mc-iframe-embed--meta--wrapper,
@@ -19,7 +19,8 @@ pub struct MediaMetadata {
                 span.mc-website--title",
         attr = "inner"
     )]
-    title: Option<String>,
+    /// Title of the linked article/video/website
+    pub title: Option<String>,
 
     #[html(selector = "span.mc-article--link,
                        span.mc-basic--link,
@@ -28,7 +29,8 @@ pub struct MediaMetadata {
                        span.mc-website--link,
                        div.mc-image--modal--element--wrapper
     ")]
-    link: Option<ResourceLink>,
+    /// Where the title links to
+    pub link: Option<ResourceLink>,
 
     #[html(
         selector = "span.mc-article--excerpt,
@@ -38,5 +40,6 @@ pub struct MediaMetadata {
                 span.mc-website--excerpt",
         attr = "inner"
     )]
-    excerpt: Option<String>,
+    /// Summary text shown below the title
+    pub excerpt: Option<String>,
 }
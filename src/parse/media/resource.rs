@@ -2,15 +2,19 @@ use super::super::util::{Identifier, ShouldSkip};
 use super::prelude::*;
 use super::simple::UrlParts;
 use std::str::FromStr;
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct ResourceLink {
-    label: Option<String>,
-    url_raw: String,
+    /// alt/title attribute or the link text
+    pub label: Option<String>,
+    /// The href/src exactly as it appears in the html
+    pub url_raw: String,
     #[serde(flatten)]
-    location: Option<UrlParts>,
+    /// The parsed url, unset when it isn't a valid url
+    pub location: Option<UrlParts>,
+    /// Only set for media hosted by parler
     #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
     #[serde(flatten)]
-    id: Option<Identifier>,
+    pub id: Option<Identifier>,
 }
 
 impl FromHtml for ResourceLink {
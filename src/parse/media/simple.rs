@@ -2,11 +2,11 @@ use super::super::util::{ShouldSkip, UntrimmedString};
 use super::prelude::*;
 use std::{result::Result, str::FromStr};
 use url::Url;
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[html(selector = "img")]
 pub struct SimpleImage {
     #[html(attr = "src")]
-    url_raw: String,
+    pub url_raw: String,
     #[serde(flatten)]
     #[html(attr = "src")]
     pub location: UrlParts,
@@ -16,29 +16,31 @@ pub struct SimpleImage {
     pub id: Option<IDFromUrl>,
 }
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[html(selector = "a")]
 pub struct Link {
     #[html(attr = "href")]
-    url_raw: String,
+    pub url_raw: String,
     #[serde(flatten)]
     #[html(attr = "href")]
-    location: Option<UrlParts>,
+    pub location: Option<UrlParts>,
     #[html(attr = "inner")]
-    label: Option<UntrimmedString>,
+    pub label: Option<UntrimmedString>,
     #[html(attr = "href")]
     #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
     #[serde(flatten)]
-    id: Option<IDFromUrl>,
+    pub id: Option<IDFromUrl>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, FromText)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromText, JsonSchema)]
 pub struct UrlParts {
-    url: String,
+    /// Absolute url, relative urls are resolved against https://parler.com/
+    pub url: String,
     #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
-    host: Option<String>,
+    pub host: Option<String>,
+    /// false for parler.com and its subdomains
     #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
-    is_external: Option<bool>,
+    pub is_external: Option<bool>,
 }
 
 impl FromStr for UrlParts {
@@ -2,14 +2,19 @@ use std::{ffi::OsStr, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
 use anyhow::*;
 use super::derive::*;
 use super::util::ShouldSkip;
+use super::post::ResolveTimestamps;
 
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct FileMeta {
     #[serde(serialize_with = "serialize_path")]
+    #[schemars(with = "String")]
     pub path: PathBuf,
-    create_dt: Option<u64>,
-    modified_dt: Option<u64>
+    pub create_dt: Option<u64>,
+    pub modified_dt: Option<u64>,
+    /// `path` below the directory that was walked, not set for files that were given directly
+    #[serde(skip)]
+    pub relative_path: Option<PathBuf>,
 }
 
 fn serialize_path<S>(v : &Path, serializer: S) -> Result<S::Ok, S::Error>
@@ -18,16 +23,45 @@ where
     serializer.serialize_str(grep_cli::escape_os(v.as_os_str()).as_str())
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct WarcMeta {
+    #[serde(rename = "warc_target_uri")]
+    #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
+    pub target_uri: Option<String>,
+    #[serde(rename = "warc_date")]
+    #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
+    pub date: Option<String>,
+    #[serde(rename = "warc_record_id")]
+    #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
+    pub record_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum InputKind {
     HTML, WARC
 }
 
+impl InputKind {
+    pub fn from_path(path: &Path) -> Self {
+        // look through compression extensions (page.warc.gz)
+        let path = match path.extension().and_then(OsStr::to_str) {
+            Some(ext) if crate::decompress::Compression::from_extension(ext).is_some() => {
+                Path::new(path.file_stem().unwrap_or_default())
+            }
+            _ => path,
+        };
+        match path.extension().and_then(OsStr::to_str) {
+            Some(ext) if ext.eq_ignore_ascii_case("warc") => InputKind::WARC,
+            _ => InputKind::HTML,
+        }
+    }
+}
+
 impl std::default::Default for InputKind{
         fn default() -> Self { InputKind::HTML }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct ScrapeMeta {
     #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
     pub source: Option<String>,
@@ -36,12 +70,18 @@ pub struct ScrapeMeta {
     #[serde(flatten)]
     pub file: Option<FileMeta>,
     #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
+    #[serde(flatten)]
+    pub warc: Option<WarcMeta>,
+    #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
     pub parse_dt: Option<u64>,
+    /// The time relative timestamps were resolved against
+    #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
+    pub reference_dt: Option<u64>,
 }
 
 
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct ParseOutput {
     #[serde(rename="__meta")]
     pub meta : ScrapeMeta,
@@ -50,13 +90,17 @@ pub struct ParseOutput {
 
 }
 
+#[derive(Clone)]
 pub struct OutputBuilder {
     path: PathBuf,
+    relative_path: Option<PathBuf>,
     create_dt: Option<u64>,
     modify_dt: Option<u64>,
     source: Option<String>,
     sha1: Option<String>,
+    warc: Option<WarcMeta>,
     parse_dt: Option<u64>,
+    reference_dt: Option<u64>,
     kind: InputKind
 }
 
@@ -89,9 +133,12 @@ impl OutputBuilder {
     pub fn new(kind: InputKind, path: PathBuf) -> Self {
         Self {
             path: path,
+            relative_path: None,
             source: None,
             sha1: None,
+            warc: None,
             parse_dt: None,
+            reference_dt: None,
             create_dt: None,
             modify_dt: None,
             kind,
@@ -100,7 +147,37 @@ impl OutputBuilder {
     pub fn path(&self) -> &Path {
         self.path.as_path()
     }
+    // Builder for a file inside of an archive, the path becomes `archive.tar!/inner/page.html`
+    pub fn archive_member(&self, member: &Path, modified: Option<u64>) -> Self {
+        let member_of = |path: &Path| {
+            let mut path = path.as_os_str().to_os_string();
+            path.push("!/");
+            path.push(member.as_os_str());
+            PathBuf::from(path)
+        };
+        Self {
+            path: member_of(&self.path),
+            relative_path: self.relative_path.as_deref().map(member_of),
+            create_dt: None,
+            modify_dt: modified,
+            kind: InputKind::from_path(member),
+            ..self.clone()
+        }
+    }
+    pub fn relative_path(&self) -> Option<&Path> {
+        self.relative_path.as_deref()
+    }
+    pub fn kind(&self) -> InputKind {
+        self.kind
+    }
+    pub fn warc_record_id(&self) -> Option<&str> {
+        self.warc.as_ref().and_then(|v| v.record_id.as_deref())
+    }
     pub fn entry<'a>(&'a mut self, entry: &walkdir::DirEntry) -> &'a mut Self {
+        self.entry_at(entry, entry.depth())
+    }
+    // for entries that didn't come out of a walk of their root, `depth` is how far below it
+    pub fn entry_at<'a>(&'a mut self, entry: &walkdir::DirEntry, depth: usize) -> &'a mut Self {
         match entry.metadata().ok() {
             Some(meta) => {
                 self.create_dt = meta.created().ok().map(to_ts).flatten();
@@ -108,18 +185,43 @@ impl OutputBuilder {
             },
             _ => { self.create_dt = None; self.modify_dt = None; }
         };
-        
+        // the walk root is `depth` levels up
+        self.relative_path = match depth {
+            0 => None,
+            depth => entry
+                .path()
+                .ancestors()
+                .nth(depth)
+                .and_then(|root| entry.path().strip_prefix(root).ok())
+                .map(Path::to_path_buf),
+        };
         self
     }
     impl_optional_builder_methods!(
         source: Option<String>,
         create_dt: Option<u64>,
-        modify_dt: Option<u64>
+        modify_dt: Option<u64>,
+        warc: Option<WarcMeta>,
+        reference_dt: Option<u64>
     );
     impl_required_builder_methods!(
         sha1: String
     );
-    pub fn build(self, page: super::page::ParlerPage) -> Result<ParseOutput> {
+    // an explicit reference time wins, otherwise use the best guess of when the page was scraped
+    fn resolve_reference_dt(&self) -> Option<u64> {
+        let warc_dt = self
+            .warc
+            .as_ref()
+            .and_then(|v| v.date.as_deref())
+            .and_then(|v| chrono::DateTime::parse_from_rfc3339(v).ok())
+            .map(|v| v.timestamp() as u64);
+        self.reference_dt.or(warc_dt).or(self.modify_dt)
+    }
+    pub fn build(self, mut page: super::page::ParlerPage) -> Result<ParseOutput> {
+        let reference_dt = self.resolve_reference_dt();
+        if let Some(reference) = reference_dt {
+            page.resolve_timestamps(reference);
+        }
         Ok(ParseOutput {
             meta : ScrapeMeta {
                 source: self.source,
@@ -129,10 +231,12 @@ impl OutputBuilder {
                         path: self.path,
                         create_dt: self.create_dt,
                         modified_dt: self.modify_dt,
-                        
+                        relative_path: self.relative_path,
                     })
                 } else { None },
-                parse_dt: to_ts(SystemTime::now())
+                warc: self.warc,
+                parse_dt: to_ts(SystemTime::now()),
+                reference_dt,
             },
             page
             
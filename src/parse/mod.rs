@@ -31,6 +31,7 @@ pub mod parser {
     pub use super::util::macros::*;
 }
 mod derive {
+    pub use schemars::JsonSchema;
     pub use serde::{Deserialize, Serialize};
     pub use unhtml_derive::{FromHtml, FromText};
 }
@@ -3,21 +3,36 @@ use std::str::FromStr;
 use super::derive::*;
 use super::profile::Author;
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[html(selector = "head")]
 pub struct OGMeta {
     #[html(selector = "meta[property='og:title']", attr = "content")]
-    title: String,
+    /// The og:title, "<username> - <name> -" for posts
+    pub title: String,
     #[html(selector = "meta[property='og:title']", attr = "content")]
-    owner: PageAuthor,
+    /// Parsed from the og:title, it's the only place the author is available for echoes
+    pub owner: PageAuthor,
     #[html(selector = "meta[property='og:url']", attr = "content")]
-    url: String,
+    /// The og:url, the path of the page on parler.com
+    pub url: String,
     #[html(selector = "meta[property='og:image']", attr = "content")]
-    image_url: Option<String>,
+    /// The og:image, usually the avatar of the author
+    pub image_url: Option<String>,
 }
 
-#[derive(FromText, Debug, PartialEq, Serialize, Deserialize)]
-struct PageAuthor(Author);
+#[derive(FromText, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PageAuthor(pub Author);
+
+impl AsRef<Author> for PageAuthor {
+    fn as_ref(&self) -> &Author {
+        &self.0
+    }
+}
+impl From<PageAuthor> for Author {
+    fn from(value: PageAuthor) -> Self {
+        value.0
+    }
+}
 impl FromStr for PageAuthor {
     type Err = unhtml::Error;
 
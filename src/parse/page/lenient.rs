@@ -0,0 +1,198 @@
+use super::super::derive::*;
+use super::super::opengraph::OGMeta;
+use super::super::parser::ElemIter;
+use super::super::post::{Comment, ParlerPost, PostCard, PostCardType, RelTimestamp};
+use super::{PageParts, ParlerPage};
+use unhtml::FromHtml;
+
+/// Something a lenient parse had to leave out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ParseWarning {
+    /// Path of the field in the output, e.g. `posts[2].cards[0].rel_timestamp`
+    pub field: String,
+    /// Why it couldn't be parsed, as reported by unhtml
+    pub error: String,
+}
+
+// Keeps the error instead of failing the parent
+struct Recover<T>(unhtml::Result<T>);
+
+impl<T: FromHtml> FromHtml for Recover<T> {
+    fn from_elements(select: ElemIter) -> unhtml::Result<Self> {
+        Ok(Recover(T::from_elements(select)))
+    }
+}
+
+type LenientCard = PostCard<Recover<PostCardType>, Recover<RelTimestamp>>;
+type LenientPost = ParlerPost<LenientCard, Recover<Comment>>;
+
+#[derive(Default)]
+struct Warnings(Vec<ParseWarning>);
+
+impl Warnings {
+    fn keep<T>(&mut self, field: String, value: Recover<T>) -> Option<T> {
+        match value.0 {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.0.push(ParseWarning {
+                    field,
+                    error: e.to_string(),
+                });
+                None
+            }
+        }
+    }
+
+    fn post(&mut self, field: &str, post: LenientPost) -> ParlerPost {
+        let cards = post
+            .cards
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, card)| self.card(&format!("{}.cards[{}]", field, i), card))
+            .collect();
+        let comments = post
+            .comments
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, v)| self.keep(format!("{}.comments[{}]", field, i), v))
+            .collect();
+        ParlerPost {
+            echo_by: post.echo_by,
+            cards,
+            comments,
+            post_id: post.post_id,
+            mentions: post.mentions,
+            engagements: post.engagements,
+        }
+    }
+
+    // a card without a kind can't be told apart from the rest of the echo chain, so it's
+    // dropped. A missing timestamp only leaves it empty.
+    fn card(&mut self, field: &str, card: LenientCard) -> Option<PostCard> {
+        let kind = self.keep(format!("{}.kind", field), card.kind)?;
+        let rel_timestamp = self
+            .keep(format!("{}.rel_timestamp", field), card.rel_timestamp)
+            .unwrap_or(RelTimestamp {
+                rel_ts: None,
+                approx_ts_offset: None,
+                created_at: Default::default(),
+            });
+        Some(PostCard {
+            kind,
+            author: card.author,
+            rel_timestamp,
+            body: card.body,
+            impression_count: card.impression_count,
+            media_container: card.media_container,
+        })
+    }
+}
+
+pub(super) fn parse(select: ElemIter) -> unhtml::Result<ParlerPage> {
+    let page = PageParts::<Recover<OGMeta>, LenientPost>::from_elements(select)?;
+    let mut warnings = Warnings::default();
+    let opengraph_meta = warnings.keep("opengraph_meta".into(), page.opengraph_meta);
+    let posts = page
+        .posts
+        .into_iter()
+        .enumerate()
+        .map(|(i, post)| warnings.post(&format!("posts[{}]", i), post))
+        .collect();
+    Ok(ParlerPage {
+        opengraph_meta,
+        posts,
+        profile: page.profile,
+        warnings: warnings.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::util::UntrimmedString;
+    use unhtml::scraper::{Html, Selector};
+
+    #[test]
+    fn missing_og_tags() {
+        let doc = Html::parse_document("<html><head></head><body><main></main></body></html>");
+        let sel = Selector::parse(":root").unwrap();
+        assert!(ParlerPage::from_elements(&mut doc.select(&sel)).is_err());
+
+        let page = parse(&mut doc.select(&sel)).unwrap();
+        assert_eq!(page.opengraph_meta, None);
+        assert!(page.posts.is_empty());
+        assert_eq!(page.warnings.len(), 1);
+        assert_eq!(page.warnings[0].field, "opengraph_meta");
+    }
+
+    fn card(kind: unhtml::Result<PostCardType>, ts: unhtml::Result<RelTimestamp>) -> LenientCard {
+        PostCard {
+            kind: Recover(kind),
+            author: None,
+            rel_timestamp: Recover(ts),
+            body: Some(UntrimmedString("body".into())),
+            impression_count: None,
+            media_container: None,
+        }
+    }
+
+    fn comment(body: &str) -> Comment {
+        Comment {
+            author: None,
+            rel_timestamp: timestamp(),
+            body: Some(UntrimmedString(body.into())),
+            engagements: None,
+            media_container: None,
+            comment_id: None,
+            replies: None,
+        }
+    }
+
+    fn timestamp() -> RelTimestamp {
+        RelTimestamp {
+            rel_ts: Some("3 days ago".into()),
+            approx_ts_offset: None,
+            created_at: Default::default(),
+        }
+    }
+
+    #[test]
+    fn keeps_the_rest_of_a_post() {
+        let broken = || unhtml::Error::TextParseError {
+            text: "?".into(),
+            type_name: "Test".into(),
+            err: "broken".into(),
+        };
+        let post: LenientPost = ParlerPost {
+            echo_by: None,
+            cards: vec![
+                card(Err(broken()), Ok(timestamp())),
+                card(Ok(PostCardType::EchoParent), Err(broken())),
+                card(Ok(PostCardType::EchoRoot), Ok(timestamp())),
+            ],
+            comments: vec![Recover(Err(broken())), Recover(Ok(comment("fine")))],
+            post_id: None,
+            mentions: None,
+            engagements: None,
+        };
+        let mut warnings = Warnings::default();
+        let post = warnings.post("posts[3]", post);
+
+        let fields: Vec<_> = warnings.0.iter().map(|v| v.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "posts[3].cards[0].kind",
+                "posts[3].cards[1].rel_timestamp",
+                "posts[3].comments[0]"
+            ]
+        );
+        assert_eq!(warnings.0[0].error, "? cannot be parsed as Test: broken");
+        // the card without a kind is dropped, the one without a timestamp kept
+        let kinds: Vec<_> = post.cards.iter().map(|v| v.kind.clone()).collect();
+        assert_eq!(kinds, vec![PostCardType::EchoParent, PostCardType::EchoRoot]);
+        assert_eq!(post.cards[0].rel_timestamp.rel_ts, None);
+        assert_eq!(post.cards[1].rel_timestamp, timestamp());
+        assert_eq!(post.comments, vec![comment("fine")]);
+    }
+}
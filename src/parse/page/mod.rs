@@ -1,11 +1,63 @@
+mod lenient;
+
 use super::derive::*;
-use super::{opengraph::OGMeta, post::ParlerPost, profile::ParlerProfile, util::ShouldSkip};
+use super::parser::ElemIter;
+use super::{
+    opengraph::OGMeta,
+    post::{ParlerPost, ResolveTimestamps},
+    profile::ParlerProfile,
+    util::ShouldSkip,
+};
+use unhtml::FromHtml;
+
+pub use lenient::ParseWarning;
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct ParlerPage {
-    pub opengraph_meta: OGMeta,
-    #[html(selector = "main div.post--card--wrapper")]
+    /// Always set unless the page was parsed leniently and the og tags were broken
+    #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
+    pub opengraph_meta: Option<OGMeta>,
     pub posts: Vec<ParlerPost>,
     #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
     pub profile: Option<ParlerProfile>,
+    /// Parts of the page that were left out by a lenient parse
+    #[serde(rename = "__warnings", default)]
+    #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
+    pub warnings: Vec<ParseWarning>,
+}
+
+// Both parses read the page through this, the lenient one with the parts that can fail wrapped.
+// The regular parse is all or nothing, the og tags are required.
+#[derive(FromHtml)]
+struct PageParts<Meta: FromHtml, Post: FromHtml> {
+    opengraph_meta: Meta,
+    #[html(selector = "main div.post--card--wrapper")]
+    posts: Vec<Post>,
+    profile: Option<ParlerProfile>,
+}
+
+impl FromHtml for ParlerPage {
+    fn from_elements(select: ElemIter) -> unhtml::Result<Self> {
+        let page = PageParts::<OGMeta, ParlerPost>::from_elements(select)?;
+        Ok(Self {
+            opengraph_meta: Some(page.opengraph_meta),
+            posts: page.posts,
+            profile: page.profile,
+            warnings: Vec::new(),
+        })
+    }
+}
+
+impl ParlerPage {
+    /// Parses whatever can be parsed. Broken og tags, cards, timestamps and comments are left
+    /// out and described in `warnings` instead of failing the whole page.
+    pub fn from_elements_lenient(select: ElemIter) -> unhtml::Result<Self> {
+        lenient::parse(select)
+    }
+}
+
+impl ResolveTimestamps for ParlerPage {
+    fn resolve_timestamps(&mut self, reference: u64) {
+        self.posts.resolve_timestamps(reference)
+    }
 }
@@ -3,10 +3,13 @@ use super::super::profile::Author;
 use super::prelude::*;
 use super::timestamp::*;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum PostCardType {
+    /// A regular post
     Post,
+    /// The echo itself, with the comment the echoing user added (if any)
     EchoParent,
+    /// The original post that was echoed
     EchoRoot,
 }
 
@@ -28,25 +31,39 @@ impl FromHtml for PostCardType {
         }
     }
 }
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
-pub struct PostCard {
-    kind: PostCardType,
+// The lenient parse reads cards with the fields that can fail wrapped, see page::lenient
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "PostCard")]
+pub struct PostCard<Kind: FromHtml = PostCardType, Ts: FromHtml = RelTimestamp> {
+    /// Whether this is a regular post or part of an echo
+    pub kind: Kind,
     #[html(
         selector = "div.card--header  a.card-meta--row, div.card--header span.card-meta--row, div.card--header div.ch--avatar-col,  div.ch--avatar--wrapper"
     )]
-    author: Option<Author>,
+    /// Author of the card, can be missing on echoes, see `OGMeta::owner`
+    pub author: Option<Author>,
     #[serde(flatten)]
     #[html(selector = "div.card--header span.card-meta--row span.post--timestamp")]
-    rel_timestamp: RelTimestamp,
+    /// When the card was posted, relative to the time of the scrape
+    pub rel_timestamp: Ts,
     #[html(selector = "div.card--body > p", attr = "inner")]
-    body: Option<UntrimmedString>,
+    /// Text of the card
+    pub body: Option<UntrimmedString>,
     #[html(
         selector = "span.card-meta--row span.impressions--wrapper span.impressions--count",
         attr = "inner"
     )]
-    impression_count: Option<i64>,
+    /// Number of times the card was viewed
+    pub impression_count: Option<i64>,
     #[html(selector = ":scope > div.card--body
        ")]
     #[serde(flatten)]
-    media_container: Option<MediaContainer>,
+    /// Attached media (images, videos, link previews...)
+    pub media_container: Option<MediaContainer>,
+}
+
+impl ResolveTimestamps for PostCard {
+    fn resolve_timestamps(&mut self, reference: u64) {
+        self.rel_timestamp.resolve(reference)
+    }
 }
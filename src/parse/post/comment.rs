@@ -4,52 +4,69 @@ use super::{
     timestamp::*,
 };
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Comment {
     #[html(
         selector = "div.card--comment-container div.card--header div.ch--meta-col, div.card--comment-container div.card--header div.ch--avatar-col"
     )]
-    author: Option<Author>,
+    /// Author of the comment
+    pub author: Option<Author>,
     #[serde(flatten)]
     #[html(
         selector = "div.card--comment-container div.card--header span.card-meta--row span.post--timestamp"
     )]
-    rel_timestamp: RelTimestamp,
+    /// When the comment was posted, relative to the time of the scrape
+    pub rel_timestamp: RelTimestamp,
 
     #[html(
         selector = "div.card--comment-container div.card--body p",
         attr = "inner"
     )]
-    body: Option<UntrimmedString>,
+    /// Text of the comment
+    pub body: Option<UntrimmedString>,
     #[html(selector = "div.card--comment-container div.card--footer div.comment--actions")]
-    engagements: Option<CommentCounts>,
+    /// Reply and vote counts
+    pub engagements: Option<CommentCounts>,
 
     #[html(selector = ":scope > div.card--comment-container div.card--body")]
     #[serde(flatten)]
-    media_container: Option<MediaContainer>,
+    /// Attached media (images, videos, link previews...)
+    pub media_container: Option<MediaContainer>,
 
     // post/026d108991b44cffbb394497aad428e4
     #[html(selector = "div.replies-list--container", attr = "id")]
-    comment_id: Option<IDFromSuffix>,
+    /// Taken from the id of the replies list
+    pub comment_id: Option<IDFromSuffix>,
     #[html(selector = "div.replies-list--container > div.reply--card--wrapper")]
-    replies: Option<Vec<Comment>>,
+    /// Replies shown below the comment, parler only includes the first few
+    pub replies: Option<Vec<Comment>>,
 }
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+impl ResolveTimestamps for Comment {
+    fn resolve_timestamps(&mut self, reference: u64) {
+        self.rel_timestamp.resolve(reference);
+        self.replies.resolve_timestamps(reference);
+    }
+}
+
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct CommentCounts {
     #[html(
         selector = ".ca--item--wrapper:nth-child(1) span.ca--item--count",
         attr = "inner"
     )]
-    reply_count: Option<i64>,
+    /// Number of replies, including the ones not on the page
+    pub reply_count: Option<i64>,
     #[html(
         selector = ".ca--item--wrapper:nth-child(2) span.ca--item--count",
         attr = "inner"
     )]
-    downvote_count: Option<i64>,
+    /// Number of downvotes
+    pub downvote_count: Option<i64>,
     #[html(
         selector = ".ca--item--wrapper:nth-child(3) span.ca--item--count",
         attr = "inner"
     )]
-    upvote_count: Option<i64>,
+    /// Number of upvotes
+    pub upvote_count: Option<i64>,
 }
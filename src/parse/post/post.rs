@@ -1,58 +1,100 @@
-use super::{super::media::SimpleImage, card::*, comment::*, prelude::*, timestamp::RelTimestamp};
+use super::{
+    super::media::SimpleImage,
+    card::*,
+    comment::*,
+    prelude::*,
+    timestamp::{RelTimestamp, ResolveTimestamps},
+};
 
 use std::str::FromStr;
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
-pub struct ParlerPost {
-    echo_by: Option<EchoBy>,
+// The lenient parse reads posts with the cards and comments that can fail wrapped, see
+// page::lenient
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "ParlerPost")]
+pub struct ParlerPost<Card: FromHtml = PostCard, Reply: FromHtml = Comment> {
+    /// Set when the post shows up on the page because someone echoed it
+    pub echo_by: Option<EchoBy>,
     #[html(
         selector = "div.card--post-container span.post,div.card--post-container span.echo--parent, div.card--post-container span.echo--root"
     )]
-    cards: Vec<PostCard>,
+    /// The post itself, or the echo chain (parent/root) for echoes
+    pub cards: Vec<Card>,
     #[html(selector = "div.comments-list--container div.comment--card--wrapper")]
-    comments: Vec<Comment>,
+    /// Comments shown below the post, parler only includes the first few
+    pub comments: Vec<Reply>,
     // we get grab the post id from the comments
     #[html(selector = "div.comments-list--container", attr = "id")]
-    post_id: Option<IDFromSuffix>,
+    /// Taken from the id of the comments list, missing when there is none
+    pub post_id: Option<IDFromSuffix>,
     #[html(selector = "div.card--body > p a.at", attr = "inner")]
-    mentions: Option<Vec<String>>,
+    /// Every @username mentioned in the body of any of the cards
+    pub mentions: Option<Vec<String>>,
     #[html(selector = "div.card--footer div.post--actions")]
-    engagements: Option<PostCounts>,
+    /// Comment, echo and upvote counts
+    pub engagements: Option<PostCounts>,
 }
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+impl ResolveTimestamps for ParlerPost {
+    fn resolve_timestamps(&mut self, reference: u64) {
+        self.echo_by.resolve_timestamps(reference);
+        self.cards.resolve_timestamps(reference);
+        self.comments.resolve_timestamps(reference);
+    }
+}
+
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct PostCounts {
     #[html(
         selector = ".pa--item--wrapper:nth-child(1) span.pa--item--count",
         attr = "inner"
     )]
-    comment_count: Option<i64>,
+    /// Number of comments, including the ones not on the page
+    pub comment_count: Option<i64>,
     #[html(
         selector = ".pa--item--wrapper:nth-child(2) span.pa--item--count",
         attr = "inner"
     )]
-    echo_count: Option<i64>,
+    /// Number of echoes
+    pub echo_count: Option<i64>,
     #[html(
         selector = ".pa--item--wrapper:nth-child(3) span.pa--item--count",
         attr = "inner"
     )]
-    upvote_count: Option<i64>,
+    /// Number of upvotes
+    pub upvote_count: Option<i64>,
 }
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[html(selector = "div.card--post-container > div.echo-byline--wrapper")]
-struct EchoBy {
+pub struct EchoBy {
+    /// Display name of the user that echoed the post
     #[html(selector = "div.eb--statement", attr = "inner")]
-    name: EchoByAuthor,
+    pub name: EchoByAuthor,
     #[html(selector = "div.eb--profile-pic")]
-    avatar: Option<SimpleImage>,
+    /// Avatar of the user that echoed the post
+    pub avatar: Option<SimpleImage>,
+    /// When the echo happened, not when the echoed post was created
     #[html(selector = "div.eb--timestamp span.reblock")]
     #[serde(flatten)]
-    rel_ts: Option<RelTimestamp>,
+    pub rel_ts: Option<RelTimestamp>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, FromText)]
-struct EchoByAuthor(String);
+impl ResolveTimestamps for EchoBy {
+    fn resolve_timestamps(&mut self, reference: u64) {
+        self.rel_ts.resolve_timestamps(reference)
+    }
+}
+
+/// Name from the "Echoed By <name>" byline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromText, JsonSchema)]
+pub struct EchoByAuthor(pub String);
+
+impl AsRef<str> for EchoByAuthor {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
 
 impl FromStr for EchoByAuthor {
     type Err = unhtml::Error;
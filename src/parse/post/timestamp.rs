@@ -3,36 +3,254 @@ use std::result::Result;
 use std::str::FromStr;
 use unhtml::Error;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, FromText)]
-pub struct ApproxRelTimestampOffset(i64);
+/// Offset in seconds from the time the page was scraped, always negative. Parler only shows
+/// coarse relative times so this is a range, `offset` is the best guess within it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromText, JsonSchema)]
+pub struct ApproxRelTimestampOffset {
+    /// Best guess, assumes the displayed number is exact
+    #[serde(rename = "approx_ts_offset")]
+    pub offset: i64,
+    /// Furthest in the past it could be
+    #[serde(rename = "approx_ts_offset_min")]
+    pub min: i64,
+    /// Most recent it could be
+    #[serde(rename = "approx_ts_offset_max")]
+    pub max: i64,
+}
 
 impl std::str::FromStr for ApproxRelTimestampOffset {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut split = s.split_whitespace();
-        let num = split.next().ok_or(())?.parse::<i64>().map_err(|_| ())?;
-        let unit = split.next().ok_or(())?;
-        let mult: i64 = match unit {
-            "second" | "seconds" => 1,
-            "minute" | "minutes" => 60,
-            "hour" | "hours" => 60 * 60,
-            "day" | "days" => 60 * 60 * 24,
-            "week" | "weeks" => 60 * 60 * 24 * 7,
-            // fuzzy
-            "month" | "months" => 60 * 60 * 24 * 30,
-            "year" | "years" => 60 * 60 * 24 * 30 * 365,
-            _ => Err(())?,
-        };
+        let (offset, min, max) = parse_relative(s).ok_or_else(|| Error::TextParseError {
+            text: s.into(),
+            type_name: "ApproxRelTimestampOffset".into(),
+            err: "unrecognized relative timestamp".into(),
+        })?;
+        Ok(Self { offset, min, max })
+    }
+}
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+
+// (names, shortest, average, longest) length of a unit in seconds.
+// months and years vary in length, the range has to account for that.
+const UNITS: &[(&[&str], i64, i64, i64)] = &[
+    (&["s", "sec", "secs", "second", "seconds"], 1, 1, 1),
+    (&["m", "min", "mins", "minute", "minutes"], MINUTE, MINUTE, MINUTE),
+    (&["h", "hr", "hrs", "hour", "hours"], HOUR, HOUR, HOUR),
+    (&["d", "day", "days"], DAY, DAY, DAY),
+    (&["w", "wk", "wks", "week", "weeks"], 7 * DAY, 7 * DAY, 7 * DAY),
+    // 30.436875 days on average
+    (&["mo", "mos", "month", "months"], 28 * DAY, 2_629_746, 31 * DAY),
+    // 365.2425 days on average
+    (&["y", "yr", "yrs", "year", "years"], 365 * DAY, 31_556_952, 366 * DAY),
+];
 
-        Ok(Self(num * mult * -1))
+fn unit(name: &str) -> Option<(i64, i64, i64)> {
+    UNITS
+        .iter()
+        .find(|(names, ..)| names.contains(&name))
+        .map(|&(_, shortest, average, longest)| (shortest, average, longest))
+}
+
+// Returns (offset, min, max) in seconds relative to now, all <= 0
+fn parse_relative(s: &str) -> Option<(i64, i64, i64)> {
+    let s = s.trim().to_ascii_lowercase();
+    let s = s.strip_suffix("ago").unwrap_or(&s).trim();
+    match s {
+        "now" | "just now" => return Some((0, -MINUTE + 1, 0)),
+        "a few seconds" => return Some((-MINUTE / 2, -MINUTE + 1, 0)),
+        "yesterday" => return Some((-DAY, -2 * DAY, 0)),
+        _ => {}
+    }
+    let mut split = s.split_whitespace();
+    let first = split.next()?;
+    let (num, name) = match first {
+        "a" | "an" => (1, split.next()?),
+        _ => match first.parse::<i64>() {
+            Ok(num) => (num, split.next()?),
+            // abbreviated, 3h / 2d / 5mo
+            Err(_) => {
+                let split_at = first.find(|c: char| !c.is_ascii_digit())?;
+                let (num, name) = first.split_at(split_at);
+                (num.parse::<i64>().ok()?, name)
+            }
+        },
+    };
+    if split.next().is_some() || num < 0 {
+        return None;
     }
+    let (shortest, average, longest) = unit(name)?;
+    // the display is rounded to the unit, so allow half a unit either way. Numbers too big
+    // for that are garbage, not a date.
+    let offset = num.checked_mul(average)?;
+    let min = num.checked_mul(longest)?.checked_add(average / 2)?;
+    let max = (num.checked_mul(shortest)? - average / 2).max(0);
+    Some((-offset, -min, -max))
+}
+
+fn format_ts(ts: i64) -> Option<String> {
+    chrono::DateTime::from_timestamp(ts, 0)
+        .map(|v| v.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, FromHtml)]
+/// Absolute creation time, only available when the output has a reference time
+/// (`reference_dt` in `__meta`) to resolve the relative timestamp against.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ApproxCreatedAt {
+    /// Best guess as an RFC 3339 timestamp
+    #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
+    pub approx_created_at: Option<String>,
+    /// Lower bound of the uncertainty window, the display unit only tells us so much
+    #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
+    pub approx_created_at_min: Option<String>,
+    /// Upper bound of the uncertainty window
+    #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
+    pub approx_created_at_max: Option<String>,
+}
+
+// there is nothing to read from the html, it's filled in by RelTimestamp::resolve
+impl FromHtml for ApproxCreatedAt {
+    fn from_elements(_select: ElemIter) -> unhtml::Result<Self> {
+        Ok(Self::default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromHtml, JsonSchema)]
 pub struct RelTimestamp {
     #[html(attr = "inner")]
-    rel_ts: Option<String>,
+    /// The timestamp as displayed, e.g. "3 weeks ago"
+    pub rel_ts: Option<String>,
     #[html(attr = "inner")]
-    approx_ts_offset: Option<ApproxRelTimestampOffset>,
+    #[serde(flatten)]
+    /// The timestamp as an offset from the time of the scrape
+    pub approx_ts_offset: Option<ApproxRelTimestampOffset>,
+    #[serde(flatten)]
+    /// The offset applied to the reference time, unset until resolved
+    pub created_at: ApproxCreatedAt,
+}
+
+impl RelTimestamp {
+    /// Resolves the offset range against `reference` (unix timestamp)
+    pub fn resolve(&mut self, reference: u64) {
+        let offset = match &self.approx_ts_offset {
+            Some(v) => v,
+            None => return,
+        };
+        let reference = reference as i64;
+        self.created_at = ApproxCreatedAt {
+            approx_created_at: reference.checked_add(offset.offset).and_then(format_ts),
+            approx_created_at_min: reference.checked_add(offset.min).and_then(format_ts),
+            approx_created_at_max: reference.checked_add(offset.max).and_then(format_ts),
+        };
+    }
+}
+
+/// Walks a parsed model and resolves every relative timestamp in it
+pub trait ResolveTimestamps {
+    fn resolve_timestamps(&mut self, reference: u64);
+}
+
+impl ResolveTimestamps for RelTimestamp {
+    fn resolve_timestamps(&mut self, reference: u64) {
+        self.resolve(reference)
+    }
+}
+
+impl<T: ResolveTimestamps> ResolveTimestamps for Option<T> {
+    fn resolve_timestamps(&mut self, reference: u64) {
+        if let Some(v) = self {
+            v.resolve_timestamps(reference)
+        }
+    }
+}
+
+impl<T: ResolveTimestamps> ResolveTimestamps for Vec<T> {
+    fn resolve_timestamps(&mut self, reference: u64) {
+        for v in self {
+            v.resolve_timestamps(reference)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_relative_timestamps() {
+        const MONTH: i64 = 2_629_746;
+        const YEAR: i64 = 31_556_952;
+        // (text, offset, min, max)
+        let cases: &[(&str, i64, i64, i64)] = &[
+            ("just now", 0, -59, 0),
+            ("now", 0, -59, 0),
+            ("a few seconds ago", -30, -59, 0),
+            ("1 second ago", -1, -1, -1),
+            ("30 seconds ago", -30, -30, -30),
+            ("a minute ago", -60, -90, -30),
+            ("1 minute ago", -60, -90, -30),
+            ("5 minutes ago", -300, -330, -270),
+            ("5m", -300, -330, -270),
+            ("an hour ago", -HOUR, -HOUR - HOUR / 2, -HOUR / 2),
+            ("3 hours ago", -3 * HOUR, -3 * HOUR - HOUR / 2, -3 * HOUR + HOUR / 2),
+            ("3h", -3 * HOUR, -3 * HOUR - HOUR / 2, -3 * HOUR + HOUR / 2),
+            ("3h ago", -3 * HOUR, -3 * HOUR - HOUR / 2, -3 * HOUR + HOUR / 2),
+            ("yesterday", -DAY, -2 * DAY, 0),
+            ("a day ago", -DAY, -DAY - DAY / 2, -DAY / 2),
+            ("2 days ago", -2 * DAY, -2 * DAY - DAY / 2, -2 * DAY + DAY / 2),
+            ("2d", -2 * DAY, -2 * DAY - DAY / 2, -2 * DAY + DAY / 2),
+            ("1 week ago", -7 * DAY, -7 * DAY - 7 * DAY / 2, -7 * DAY / 2),
+            ("3 weeks ago", -21 * DAY, -21 * DAY - 7 * DAY / 2, -21 * DAY + 7 * DAY / 2),
+            ("1w", -7 * DAY, -7 * DAY - 7 * DAY / 2, -7 * DAY / 2),
+            ("a month ago", -MONTH, -31 * DAY - MONTH / 2, -28 * DAY + MONTH / 2),
+            ("2 months ago", -2 * MONTH, -62 * DAY - MONTH / 2, -56 * DAY + MONTH / 2),
+            ("2mo", -2 * MONTH, -62 * DAY - MONTH / 2, -56 * DAY + MONTH / 2),
+            ("a year ago", -YEAR, -366 * DAY - YEAR / 2, -365 * DAY + YEAR / 2),
+            ("2 years ago", -2 * YEAR, -732 * DAY - YEAR / 2, -730 * DAY + YEAR / 2),
+            ("2y", -2 * YEAR, -732 * DAY - YEAR / 2, -730 * DAY + YEAR / 2),
+        ];
+        for &(text, offset, min, max) in cases {
+            assert_eq!(parse_relative(text), Some((offset, min, max)), "{}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for text in &[
+            "",
+            "ago",
+            "3",
+            "3 fortnights ago",
+            "-3 days ago",
+            "3 days 4 hours ago",
+            "h",
+            "99999999999999 years ago",
+            "9223372036854775807m",
+        ] {
+            assert_eq!(parse_relative(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn resolves_against_reference() {
+        let mut ts = RelTimestamp {
+            rel_ts: Some("3 weeks ago".into()),
+            approx_ts_offset: "3 weeks ago".parse().ok(),
+            created_at: ApproxCreatedAt::default(),
+        };
+        // 2021-01-10T00:00:00Z
+        ts.resolve(1610236800);
+        assert_eq!(
+            ts.created_at,
+            ApproxCreatedAt {
+                approx_created_at: Some("2020-12-20T00:00:00Z".into()),
+                approx_created_at_min: Some("2020-12-16T12:00:00Z".into()),
+                approx_created_at_max: Some("2020-12-23T12:00:00Z".into()),
+            }
+        );
+    }
 }
@@ -3,7 +3,7 @@ use super::super::util::ShouldSkip;
 use super::prelude::*;
 use super::Badge;
 
-#[derive(FromHtml, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(FromHtml, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Author {
     #[html(selector = "span.author--name,span.profile--name", attr = "inner")]
     pub name: Option<String>,
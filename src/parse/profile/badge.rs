@@ -4,8 +4,8 @@ use std::str::FromStr;
 use url::Url;
 
 // unhtml doesnt support derives from enums and I'm laazy
-#[derive(FromText, Debug, Serialize, Deserialize, PartialEq)]
-pub struct Badge(BadgeKind);
+#[derive(FromText, Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct Badge(pub BadgeKind);
 
 impl FromStr for Badge {
     type Err = unhtml::Error;
@@ -15,7 +15,7 @@ impl FromStr for Badge {
     }
 }
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum BadgeKind {
     Verified,
     Gold,
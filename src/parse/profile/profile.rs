@@ -1,18 +1,18 @@
 use super::super::{media::*, profile::Author};
 use super::prelude::*;
 
-#[derive(Debug, PartialEq, FromHtml, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, FromHtml, Serialize, Deserialize, JsonSchema)]
 #[html(selector = "div#hero--wrapper")]
 pub struct ParlerProfile {
     #[serde(flatten)]
     #[html(selector = "#hero")]
-    user: Author,
+    pub user: Author,
     #[html(selector = "#hero--top")]
-    banner: Option<SimpleImage>,
+    pub banner: Option<SimpleImage>,
     #[html(selector = "#hero--bottom div.profile-photo-image")]
-    avatar: Option<SimpleImage>,
+    pub avatar: Option<SimpleImage>,
     #[html(selector = "#hero--bottom span.profile--bio", attr = "inner")]
-    bio: Option<String>,
+    pub bio: Option<String>,
     #[html(selector = "#hero--bottom span.profile--bio a")]
-    bio_links: Vec<Link>,
+    pub bio_links: Vec<Link>,
 }
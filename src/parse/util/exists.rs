@@ -1,7 +1,7 @@
 use super::prelude::*;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-pub struct ElementExists(bool);
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ElementExists(pub bool);
 
 impl FromHtml for ElementExists {
     fn from_elements(select: ElemIter) -> unhtml::Result<Self> {
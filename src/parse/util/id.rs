@@ -4,7 +4,7 @@ use base62::decode;
 use std::result::Result;
 use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Identifier {
     pub id: Option<String>,
     #[serde(skip_serializing_if = "ShouldSkip::should_skip")]
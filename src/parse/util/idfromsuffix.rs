@@ -1,7 +1,18 @@
 use super::prelude::*;
 use std::result::Result;
-#[derive(FromText, Debug, PartialEq, Serialize, Deserialize)]
-pub struct IDFromSuffix(String);
+#[derive(FromText, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct IDFromSuffix(pub String);
+
+impl AsRef<str> for IDFromSuffix {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+impl From<IDFromSuffix> for String {
+    fn from(value: IDFromSuffix) -> Self {
+        value.0
+    }
+}
 
 impl FromStr for IDFromSuffix {
     type Err = unhtml::Error;
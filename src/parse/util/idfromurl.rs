@@ -2,8 +2,8 @@ use super::id::Identifier;
 use super::prelude::*;
 use std::result::Result;
 
-#[derive(FromText, Debug, PartialEq, Serialize, Deserialize)]
-pub struct IDFromUrl(Identifier);
+#[derive(FromText, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct IDFromUrl(pub Identifier);
 impl From<IDFromUrl> for String {
     fn from(v: IDFromUrl) -> Self {
         v.0.id.unwrap_or_default()
@@ -7,3 +7,9 @@ impl<T> ShouldSkip for Option<T> {
         self.is_none()
     }
 }
+
+impl<T> ShouldSkip for Vec<T> {
+    fn should_skip(&self) -> bool {
+        self.is_empty()
+    }
+}
@@ -1,8 +1,8 @@
 use super::prelude::*;
 use std::fmt::Display;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-pub struct UntrimmedString(String);
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct UntrimmedString(pub String);
 
 impl unhtml::FromText for UntrimmedString {
     fn from_inner_text(select: ElemIter) -> unhtml::Result<Self> {
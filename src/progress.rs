@@ -0,0 +1,284 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::{bounded, RecvTimeoutError, Sender};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Outcome counters, updated by the parsing threads
+#[derive(Debug)]
+pub struct Stats {
+    start: Instant,
+    discovered: AtomicU64,
+    parsed: AtomicU64,
+    skipped: AtomicU64,
+    duplicates: AtomicU64,
+    failed: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            discovered: AtomicU64::new(0),
+            parsed: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            duplicates: AtomicU64::new(0),
+            failed: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl Stats {
+    /// An input file (or stdin) was found, archives and WARC files count once
+    pub fn discovered(&self) {
+        self.discovered.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn parsed(&self) {
+        self.parsed.fetch_add(1, Ordering::Relaxed);
+    }
+    /// Left out by `--resume`
+    pub fn skipped(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn duplicate(&self) {
+        self.duplicates.fetch_add(1, Ordering::Relaxed);
+    }
+    /// `kind` is the `ProcessingError` variant
+    pub fn failed(&self, kind: &'static str) {
+        *self.failed.lock().unwrap().entry(kind).or_default() += 1;
+    }
+
+    pub fn summary(&self) -> Summary {
+        let failures: BTreeMap<String, u64> = self
+            .failed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.to_string(), *v))
+            .collect();
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let parsed = self.parsed.load(Ordering::Relaxed);
+        let failed = failures.values().sum();
+        let duplicates = self.duplicates.load(Ordering::Relaxed);
+        Summary {
+            discovered: self.discovered.load(Ordering::Relaxed),
+            parsed,
+            skipped: self.skipped.load(Ordering::Relaxed),
+            duplicates,
+            failed,
+            failures,
+            elapsed_secs: elapsed,
+            docs_per_sec: per_sec(parsed + failed + duplicates, elapsed),
+        }
+    }
+}
+
+fn per_sec(n: u64, secs: f64) -> f64 {
+    if secs > 0.0 {
+        n as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// End of run statistics, written by `--stats-json`
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub discovered: u64,
+    pub parsed: u64,
+    pub skipped: u64,
+    pub duplicates: u64,
+    pub failed: u64,
+    /// Failures by `ProcessingError` variant
+    pub failures: BTreeMap<String, u64>,
+    pub elapsed_secs: f64,
+    /// Parsed, failed and duplicate documents per second
+    pub docs_per_sec: f64,
+}
+
+impl Summary {
+    // `total` is the number of paths when it's known up front, files discovered so far are
+    // measured against it
+    fn progress_line(&self, total: Option<u64>) -> String {
+        let mut line = format!(
+            "{} files discovered, {} parsed, {} failed, {:.1} docs/s",
+            self.discovered, self.parsed, self.failed, self.docs_per_sec
+        );
+        if let Some(total) = total {
+            let rate = per_sec(self.discovered, self.elapsed_secs);
+            if rate > 0.0 && self.discovered <= total {
+                let eta = ((total - self.discovered) as f64 / rate) as u64;
+                line.push_str(&format!(
+                    ", {}/{} files, ETA {}",
+                    self.discovered,
+                    total,
+                    format_duration(eta)
+                ));
+            }
+        }
+        line
+    }
+
+    pub fn print<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "{} files discovered, {} documents parsed, {} skipped, {} duplicates, {} failed in {} ({:.1} docs/s)",
+            self.discovered,
+            self.parsed,
+            self.skipped,
+            self.duplicates,
+            self.failed,
+            format_duration(self.elapsed_secs as u64),
+            self.docs_per_sec
+        )?;
+        for (kind, count) in &self.failures {
+            writeln!(out, "  {}: {}", kind, count)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let mut out = File::create(path)
+            .with_context(|| format!("failed to create stats file {}", path.display()))?;
+        serde_json::to_writer_pretty(&mut out, self)?;
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+/// Prints messages to stderr. While the progress line is shown it's cleared first and drawn
+/// again below the message, so the two don't end up on the same line.
+#[derive(Debug, Clone, Default)]
+pub struct Stderr {
+    // the progress line as last drawn, None until the first one
+    line: Option<Arc<Mutex<Option<String>>>>,
+}
+
+impl Stderr {
+    pub fn print(&self, message: impl std::fmt::Display) {
+        match &self.line {
+            Some(line) => {
+                let line = line.lock().unwrap();
+                match &*line {
+                    Some(line) => eprint!("\r\x1b[K{}\n{}", message, line),
+                    None => eprintln!("{}", message),
+                }
+            }
+            None => eprintln!("{}", message),
+        }
+    }
+}
+
+/// Redraws a status line on stderr every second until stopped
+pub struct Progress {
+    stop: Sender<()>,
+    handle: JoinHandle<()>,
+    line: Arc<Mutex<Option<String>>>,
+}
+
+impl Progress {
+    pub fn spawn(stats: Arc<Stats>, total: Option<u64>) -> Self {
+        let (stop, stopped) = bounded(0);
+        let line = Arc::new(Mutex::new(None));
+        let drawn = Arc::clone(&line);
+        let handle = std::thread::spawn(move || loop {
+            let done = !matches!(
+                stopped.recv_timeout(Duration::from_secs(1)),
+                Err(RecvTimeoutError::Timeout)
+            );
+            let mut drawn = drawn.lock().unwrap();
+            let line = drawn.insert(stats.summary().progress_line(total));
+            eprint!("\r\x1b[K{}", line);
+            if done {
+                // messages printed after this go below the last line
+                *drawn = None;
+                eprintln!();
+                return;
+            }
+        });
+        Self { stop, handle, line }
+    }
+
+    /// Where messages go while the progress line is shown
+    pub fn stderr(&self) -> Stderr {
+        Stderr {
+            line: Some(Arc::clone(&self.line)),
+        }
+    }
+
+    pub fn finish(self) {
+        let _ = self.stop.send(());
+        let _ = self.handle.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_outcomes() {
+        let stats = Stats::default();
+        for _ in 0..4 {
+            stats.discovered();
+        }
+        stats.parsed();
+        stats.parsed();
+        stats.skipped();
+        stats.duplicate();
+        stats.failed("ParlerParseError");
+        stats.failed("FileIO");
+        stats.failed("ParlerParseError");
+        let summary = stats.summary();
+        assert_eq!(summary.discovered, 4);
+        assert_eq!(summary.parsed, 2);
+        assert_eq!(summary.failed, 3);
+        assert_eq!(summary.failures["ParlerParseError"], 2);
+        assert_eq!(summary.failures["FileIO"], 1);
+
+        let mut out = Vec::new();
+        summary.print(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\n  FileIO: 1\n  ParlerParseError: 2\n"));
+    }
+
+    #[test]
+    fn estimates_time_left() {
+        let summary = Summary {
+            discovered: 250,
+            parsed: 250,
+            skipped: 0,
+            duplicates: 0,
+            failed: 0,
+            failures: BTreeMap::new(),
+            elapsed_secs: 10.0,
+            docs_per_sec: 25.0,
+        };
+        assert_eq!(
+            summary.progress_line(Some(1000)),
+            "250 files discovered, 250 parsed, 0 failed, 25.0 docs/s, 250/1000 files, ETA 30s"
+        );
+        assert!(!summary.progress_line(None).contains("ETA"));
+        assert_eq!(format_duration(3725), "1h02m");
+    }
+}
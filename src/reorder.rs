@@ -0,0 +1,110 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+// how often a waiting `reserve` checks whether it should give up
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Passes items on in sequence order, items that arrive early are held back until the ones
+/// before them are in
+#[derive(Debug)]
+pub struct ReorderBuffer<T> {
+    next: usize,
+    pending: BTreeMap<usize, T>,
+}
+
+impl<T> Default for ReorderBuffer<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> ReorderBuffer<T> {
+    /// Adds item number `seq` (counting from 0) and returns every item that's ready now
+    pub fn push(&mut self, seq: usize, item: T) -> Vec<T> {
+        self.pending.insert(seq, item);
+        let mut ready = Vec::new();
+        while let Some(item) = self.pending.remove(&self.next) {
+            ready.push(item);
+            self.next += 1;
+        }
+        ready
+    }
+}
+
+/// A [`ReorderBuffer`] shared by the parsing threads. Items are numbered before they're handed
+/// out and at most `capacity` of them can be out at a time, counting from the next one to be
+/// released, so one slow item holds back a bounded number of others.
+#[derive(Debug)]
+pub struct SharedReorderBuffer<T> {
+    buffer: Mutex<ReorderBuffer<T>>,
+    room: Condvar,
+    capacity: usize,
+}
+
+impl<T> SharedReorderBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffer: Mutex::new(ReorderBuffer::default()),
+            room: Condvar::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Waits until item `seq` can be handed out, returns false if `stop` says so first
+    pub fn reserve(&self, seq: usize, stop: impl Fn() -> bool) -> bool {
+        let mut buffer = self.buffer.lock().unwrap();
+        while seq >= buffer.next + self.capacity {
+            if stop() {
+                return false;
+            }
+            buffer = self.room.wait_timeout(buffer, POLL_INTERVAL).unwrap().0;
+        }
+        true
+    }
+
+    /// Adds item `seq` and calls `f` with every item that's ready now, in order. Other items
+    /// wait until `f` is done with these.
+    pub fn push(&self, seq: usize, item: T, f: impl FnMut(T)) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.push(seq, item).into_iter().for_each(f);
+        self.room.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releases_in_order() {
+        let mut buffer = ReorderBuffer::default();
+        assert_eq!(buffer.push(2, "c"), Vec::<&str>::new());
+        assert_eq!(buffer.push(1, "b"), Vec::<&str>::new());
+        assert_eq!(buffer.push(0, "a"), vec!["a", "b", "c"]);
+        assert_eq!(buffer.push(4, "e"), Vec::<&str>::new());
+        assert_eq!(buffer.push(3, "d"), vec!["d", "e"]);
+    }
+
+    #[test]
+    fn bounds_items_out() {
+        let buffer = SharedReorderBuffer::new(2);
+        assert!(buffer.reserve(0, || false));
+        assert!(buffer.reserve(1, || false));
+        // 0 and 1 are still out, 2 has to wait
+        assert!(!buffer.reserve(2, || true));
+
+        let mut released = Vec::new();
+        buffer.push(1, "b", |v| released.push(v));
+        assert!(released.is_empty());
+        assert!(!buffer.reserve(2, || true));
+        buffer.push(0, "a", |v| released.push(v));
+        assert_eq!(released, vec!["a", "b"]);
+        assert!(buffer.reserve(3, || true));
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::{Context, Result};
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+// Success log lines are `<escaped path>\t<sha1>`. Escaping turns tabs in paths into `\t`
+// so the last tab always separates the two. Older logs only have the path.
+pub fn log_line(path: &Path, sha1: &str) -> String {
+    format!("{}\t{}\n", grep_cli::escape_os(path.as_os_str()), sha1)
+}
+
+/// Calls `f` with every complete line. A run that got killed mid-write leaves a partial
+/// last line behind, that one isn't trusted.
+pub fn read_lines<R: Read>(reader: R, mut f: impl FnMut(&str)) -> std::io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        match line.strip_suffix('\n') {
+            Some(v) => f(v),
+            None => return Ok(()),
+        }
+    }
+}
+
+pub fn is_sha1(v: &str) -> bool {
+    v.len() == 40 && v.bytes().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Paths and document hashes recorded by a previous run
+#[derive(Debug, Default)]
+pub struct ResumeLog {
+    paths: HashSet<String>,
+    sha1s: HashSet<String>,
+}
+
+impl ResumeLog {
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        let mut log = ResumeLog::default();
+        read_lines(reader, |line| match line.rsplit_once('\t') {
+            Some((path, sha1)) if is_sha1(sha1) => {
+                log.paths.insert(path.to_string());
+                log.sha1s.insert(sha1.to_string());
+            }
+            _ if !line.is_empty() => {
+                log.paths.insert(line.to_string());
+            }
+            _ => {}
+        })?;
+        Ok(log)
+    }
+
+    /// Reads the log at `path`, a missing log is the same as an empty one
+    pub fn open(path: &Path) -> Result<Self> {
+        match File::open(path) {
+            Ok(file) => Self::read(file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+        .with_context(|| format!("failed to read resume log {}", path.display()))
+    }
+
+    pub fn contains_path(&self, path: &Path) -> bool {
+        self.paths.contains(&grep_cli::escape_os(path.as_os_str()))
+    }
+
+    pub fn contains_sha1(&self, sha1: &str) -> bool {
+        self.sha1s.contains(sha1)
+    }
+}
+
+/// Opens a line based log for appending. A partial line left behind by a killed run is
+/// finished first so new lines don't get glued onto it.
+pub fn open_append(path: &Path) -> std::io::Result<File> {
+    // appended writes always go to the end, reading the last byte doesn't get in the way
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+    let partial_line = if file.metadata()?.len() > 0 {
+        let mut last = [0; 1];
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last)?;
+        last[0] != b'\n'
+    } else {
+        false
+    };
+    if partial_line {
+        file.write_all(b"\n")?;
+    }
+    Ok(file)
+}
+
+const SYNC_EVERY_LINES: usize = 1000;
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+// Lines are held back until `sync` so the log never claims a page the outputs haven't
+// flushed yet. Killing the run loses at most the unsynced lines, those pages get redone.
+pub struct SuccessLog {
+    file: File,
+    pending: String,
+    pending_lines: usize,
+    last_sync: Instant,
+}
+
+impl SuccessLog {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::new(File::create(path)?))
+    }
+
+    /// Opens an existing log for appending
+    pub fn append(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::new(open_append(path)?))
+    }
+
+    fn new(file: File) -> Self {
+        Self {
+            file,
+            pending: String::new(),
+            pending_lines: 0,
+            last_sync: Instant::now(),
+        }
+    }
+
+    pub fn push(&mut self, path: &Path, sha1: &str) {
+        self.pending.push_str(&log_line(path, sha1));
+        self.pending_lines += 1;
+    }
+
+    pub fn should_sync(&self) -> bool {
+        self.pending_lines >= SYNC_EVERY_LINES
+            || (self.pending_lines > 0 && self.last_sync.elapsed() >= SYNC_INTERVAL)
+    }
+
+    pub fn sync(&mut self) -> std::io::Result<()> {
+        self.file.write_all(self.pending.as_bytes())?;
+        self.file.sync_data()?;
+        self.pending.clear();
+        self.pending_lines = 0;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA1: &str = "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12";
+
+    #[test]
+    fn reads_paths_and_hashes() -> Result<()> {
+        let log = format!(
+            "{}{}old/format.html\n\npartial/line.html\t2fd4",
+            log_line(Path::new("a/post.html"), SHA1),
+            log_line(Path::new("tab\there.html"), "de9f2c7fd25e1b3afad3e85a0bd17d9b100db4b3"),
+        );
+        let log = ResumeLog::read(log.as_bytes())?;
+        assert!(log.contains_path(Path::new("a/post.html")));
+        assert!(log.contains_path(Path::new("tab\there.html")));
+        assert!(log.contains_path(Path::new("old/format.html")));
+        assert!(!log.contains_path(Path::new("partial/line.html")));
+        assert!(log.contains_sha1(SHA1));
+        assert!(log.contains_sha1("de9f2c7fd25e1b3afad3e85a0bd17d9b100db4b3"));
+        Ok(())
+    }
+
+    #[test]
+    fn appends_after_partial_line() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("parler-resume-{}.log", std::process::id()));
+        std::fs::write(&path, format!("{}b.html\t2fd4", log_line(Path::new("a.html"), SHA1)))?;
+        let mut log = SuccessLog::append(&path)?;
+        log.push(Path::new("c.html"), SHA1);
+        log.sync()?;
+        let resumed = ResumeLog::open(&path)?;
+        std::fs::remove_file(&path)?;
+        assert!(resumed.contains_path(Path::new("a.html")));
+        assert!(resumed.contains_path(Path::new("c.html")));
+        // the partial line is finished with a newline but never matches
+        assert!(!resumed.contains_path(Path::new("b.html")));
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Exit status of a run that got through every input, but some of them failed
+pub const EXIT_FAILURES: i32 = 2;
+
+/// Tells the parsing threads to stop taking new work, either because of a signal or because
+/// the writer is gone
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    signal: Arc<AtomicUsize>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    /// Stops on the first SIGINT or SIGTERM, a second one exits right away
+    pub fn on_signals() -> Result<Self> {
+        let shutdown = Self::default();
+        let signalled = Arc::new(AtomicBool::new(false));
+        for &signal in &[SIGINT, SIGTERM] {
+            signal_hook::flag::register_conditional_shutdown(
+                signal,
+                128 + signal,
+                Arc::clone(&signalled),
+            )
+            .context("failed to install signal handler")?;
+            signal_hook::flag::register(signal, Arc::clone(&signalled))
+                .context("failed to install signal handler")?;
+            signal_hook::flag::register_usize(
+                signal,
+                Arc::clone(&shutdown.signal),
+                signal as usize,
+            )
+            .context("failed to install signal handler")?;
+        }
+        Ok(shutdown)
+    }
+
+    /// Cancels once the returned guard is dropped, however the owner ends
+    pub fn cancel_on_drop(self) -> CancelOnDrop {
+        CancelOnDrop(self)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// The writer is gone, nothing can be written anymore
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.signal().is_some() || self.is_cancelled()
+    }
+
+    /// The signal that interrupted the run
+    pub fn signal(&self) -> Option<i32> {
+        match self.signal.load(Ordering::Relaxed) {
+            0 => None,
+            v => Some(v as i32),
+        }
+    }
+
+    /// 128 + the signal when interrupted, `EXIT_FAILURES` when any input failed, 0 otherwise
+    pub fn exit_code(&self, failed: u64) -> i32 {
+        match self.signal() {
+            Some(signal) => 128 + signal,
+            None if failed > 0 => EXIT_FAILURES,
+            None => 0,
+        }
+    }
+}
+
+pub struct CancelOnDrop(Shutdown);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let shutdown = Shutdown::default();
+        assert!(!shutdown.is_stopping());
+        assert_eq!(shutdown.exit_code(0), 0);
+        assert_eq!(shutdown.exit_code(3), EXIT_FAILURES);
+
+        drop(shutdown.clone().cancel_on_drop());
+        assert!(shutdown.is_stopping());
+        assert!(shutdown.is_cancelled());
+        assert_eq!(shutdown.signal(), None);
+
+        let interrupted = Shutdown::default();
+        interrupted.signal.store(SIGINT as usize, Ordering::Relaxed);
+        assert!(interrupted.is_stopping());
+        assert!(!interrupted.is_cancelled());
+        assert_eq!(interrupted.exit_code(3), 130);
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::{anyhow, Result};
+use parler_indexer::{ParlerPage, PostCard};
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+// The parser keeps these optional since plenty of real pages lack them, --strict turns them
+// back into requirements
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RequiredField {
+    /// Every post has an id
+    PostId,
+    /// Every card has an author with a username
+    AuthorUsername,
+    /// Every card has a body or at least one media item
+    BodyOrMedia,
+    /// Every card has a timestamp
+    Timestamp,
+}
+
+impl RequiredField {
+    pub const NAMES: &'static [&'static str] =
+        &["post-id", "author-username", "body-or-media", "timestamp"];
+    pub const ALL: &'static [RequiredField] = &[
+        RequiredField::PostId,
+        RequiredField::AuthorUsername,
+        RequiredField::BodyOrMedia,
+        RequiredField::Timestamp,
+    ];
+
+    fn check_card(self, card: &PostCard) -> bool {
+        match self {
+            RequiredField::PostId => true,
+            RequiredField::AuthorUsername => card
+                .author
+                .as_ref()
+                .is_some_and(|v| !v.username.trim().is_empty()),
+            RequiredField::BodyOrMedia => {
+                card.body
+                    .as_ref()
+                    .is_some_and(|v| !v.as_ref().trim().is_empty())
+                    || card
+                        .media_container
+                        .as_ref()
+                        .is_some_and(|v| !v.media_items.is_empty())
+            }
+            RequiredField::Timestamp => card.rel_timestamp.rel_ts.is_some(),
+        }
+    }
+}
+
+impl FromStr for RequiredField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "post-id" => Ok(RequiredField::PostId),
+            "author-username" => Ok(RequiredField::AuthorUsername),
+            "body-or-media" => Ok(RequiredField::BodyOrMedia),
+            "timestamp" => Ok(RequiredField::Timestamp),
+            _ => Err(anyhow!("unknown required field {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for RequiredField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(Self::NAMES[*self as usize])
+    }
+}
+
+/// A rule that failed and where in the page it failed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    pub rule: RequiredField,
+    /// Path of the post or card, e.g. `posts[2].cards[0]`
+    pub field: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violations(pub Vec<Violation>);
+
+impl fmt::Display for Violations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, v) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{} at {}", v.rule, v.field)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks every post and card against `rules`. A post without any cards fails every card rule.
+pub fn validate(page: &ParlerPage, rules: &[RequiredField]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for (i, post) in page.posts.iter().enumerate() {
+        for &rule in rules {
+            if rule == RequiredField::PostId {
+                if post.post_id.is_none() {
+                    violations.push(Violation {
+                        rule,
+                        field: format!("posts[{}]", i),
+                    });
+                }
+            } else if post.cards.is_empty() {
+                violations.push(Violation {
+                    rule,
+                    field: format!("posts[{}].cards", i),
+                });
+            }
+            for (j, card) in post.cards.iter().enumerate() {
+                if !rule.check_card(card) {
+                    violations.push(Violation {
+                        rule,
+                        field: format!("posts[{}].cards[{}]", i, j),
+                    });
+                }
+            }
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parler_indexer::parse::post::{PostCardType, RelTimestamp};
+    use parler_indexer::parse::util::{IDFromSuffix, UntrimmedString};
+    use parler_indexer::{Author, ParlerPost};
+
+    fn card(username: Option<&str>, body: Option<&str>) -> PostCard {
+        PostCard {
+            kind: PostCardType::Post,
+            author: username.map(|v| Author {
+                name: None,
+                username: v.into(),
+                badge: None,
+                avatar: None,
+            }),
+            rel_timestamp: RelTimestamp {
+                rel_ts: Some("3 weeks ago".into()),
+                approx_ts_offset: None,
+                created_at: Default::default(),
+            },
+            body: body.map(|v| UntrimmedString(v.into())),
+            impression_count: None,
+            media_container: None,
+        }
+    }
+
+    fn page(posts: Vec<(Option<&str>, Vec<PostCard>)>) -> ParlerPage {
+        ParlerPage {
+            opengraph_meta: None,
+            posts: posts
+                .into_iter()
+                .map(|(id, cards)| ParlerPost {
+                    echo_by: None,
+                    cards,
+                    comments: Vec::new(),
+                    post_id: id.map(|v| IDFromSuffix(v.into())),
+                    mentions: None,
+                    engagements: None,
+                })
+                .collect(),
+            profile: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reports_failed_rules() {
+        let page = page(vec![
+            (Some("a"), vec![card(Some("alice"), Some("hi"))]),
+            (None, vec![card(Some("bob"), Some("hi")), card(None, Some(" "))]),
+            (Some("c"), vec![]),
+        ]);
+        let violations = validate(&page, RequiredField::ALL);
+        assert_eq!(
+            Violations(violations).to_string(),
+            "post-id at posts[1], author-username at posts[1].cards[1], \
+             body-or-media at posts[1].cards[1], author-username at posts[2].cards, \
+             body-or-media at posts[2].cards, timestamp at posts[2].cards"
+        );
+        assert!(validate(&page, &[RequiredField::Timestamp])
+            .iter()
+            .all(|v| v.field == "posts[2].cards"));
+    }
+}
@@ -0,0 +1,312 @@
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use std::io::{BufRead, Read};
+
+// Minimal WARC/1.0 reader. Only what we need to pull HTML out of crawl data:
+// record headers + the raw content block, no digest verification.
+
+#[derive(Debug)]
+pub struct WarcRecord {
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub content: Vec<u8>,
+}
+
+impl WarcRecord {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+    pub fn record_type(&self) -> Option<&str> {
+        self.header("WARC-Type")
+    }
+    pub fn target_uri(&self) -> Option<&str> {
+        // some crawlers wrap the uri in angle brackets
+        self.header("WARC-Target-URI")
+            .map(|v| v.trim_start_matches('<').trim_end_matches('>'))
+    }
+    pub fn date(&self) -> Option<&str> {
+        self.header("WARC-Date")
+    }
+    pub fn record_id(&self) -> Option<&str> {
+        self.header("WARC-Record-ID")
+    }
+    pub fn is_response(&self) -> bool {
+        self.record_type()
+            .is_some_and(|v| v.eq_ignore_ascii_case("response"))
+    }
+
+    // Splits the http response stored in a `response` record into its headers and body
+    pub fn http_response(&self) -> Option<HttpResponse<'_>> {
+        let content = self.content.as_slice();
+        if !content.starts_with(b"HTTP/") {
+            return None;
+        }
+        let end = find(content, b"\r\n\r\n")?;
+        let head = String::from_utf8_lossy(&content[..end]);
+        let mut lines = head.split("\r\n");
+        let status = lines.next()?.to_string();
+        let headers = lines
+            .filter_map(|line| {
+                let mut split = line.splitn(2, ':');
+                Some((split.next()?.trim().into(), split.next()?.trim().into()))
+            })
+            .collect();
+        Some(HttpResponse {
+            status,
+            headers,
+            body: &content[end + 4..],
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpResponse<'a> {
+    pub status: String,
+    pub headers: Vec<(String, String)>,
+    pub body: &'a [u8],
+}
+
+impl<'a> HttpResponse<'a> {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+    pub fn is_html(&self) -> bool {
+        // no content type, let the html parser decide
+        self.header("Content-Type")
+            .is_none_or(|v| v.to_ascii_lowercase().contains("html"))
+    }
+    pub fn is_chunked(&self) -> bool {
+        self.header("Transfer-Encoding")
+            .is_some_and(|v| v.to_ascii_lowercase().contains("chunked"))
+    }
+    pub fn into_body(self) -> Result<Vec<u8>> {
+        let body = if self.is_chunked() {
+            dechunk(self.body)?
+        } else {
+            self.body.to_vec()
+        };
+        match self.header("Content-Encoding") {
+            Some(encoding) => decode(encoding, body),
+            None => Ok(body),
+        }
+    }
+}
+
+// Undoes the content codings in the order they were applied, listed last to first
+fn decode(encoding: &str, mut body: Vec<u8>) -> Result<Vec<u8>> {
+    for coding in encoding.rsplit(',').map(|v| v.trim().to_ascii_lowercase()) {
+        body = match coding.as_str() {
+            "" | "identity" => body,
+            "gzip" | "x-gzip" => read_all(GzDecoder::new(body.as_slice()))?,
+            // plenty of servers send raw deflate data instead of the zlib wrapped kind
+            "deflate" => read_all(ZlibDecoder::new(body.as_slice()))
+                .or_else(|_| read_all(DeflateDecoder::new(body.as_slice())))?,
+            other => bail!("unsupported Content-Encoding {:?}", other),
+        };
+    }
+    Ok(body)
+}
+
+fn read_all(mut reader: impl Read) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    reader
+        .read_to_end(&mut out)
+        .context("failed to decode response body")?;
+    Ok(out)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn dechunk(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(body.len());
+    loop {
+        let line_end = find(body, b"\r\n").ok_or_else(|| anyhow!("truncated chunk header"))?;
+        let size = std::str::from_utf8(&body[..line_end])?;
+        // ignore chunk extensions
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .with_context(|| format!("invalid chunk size {:?}", size))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if body.len() < size {
+            bail!("truncated chunk");
+        }
+        out.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
+
+pub struct WarcReader<R> {
+    inner: R,
+    done: bool,
+}
+
+impl<R: BufRead> WarcReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, done: false }
+    }
+
+    fn read_line(&mut self, line: &mut String) -> Result<usize> {
+        line.clear();
+        let read = self.inner.read_line(line)?;
+        while line.ends_with('\n') || line.ends_with('\r') {
+            line.pop();
+        }
+        Ok(read)
+    }
+
+    fn read_record(&mut self) -> Result<Option<WarcRecord>> {
+        let mut line = String::new();
+        // skip the blank lines that trail the previous record
+        loop {
+            if self.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if !line.is_empty() {
+                break;
+            }
+        }
+        if !line.starts_with("WARC/") {
+            bail!("expected WARC version line, found {:?}", line);
+        }
+        let version = line.clone();
+        let mut headers = Vec::new();
+        loop {
+            if self.read_line(&mut line)? == 0 {
+                bail!("unexpected end of file in WARC headers");
+            }
+            if line.is_empty() {
+                break;
+            }
+            match line.split_once(':') {
+                Some((k, v)) => headers.push((k.trim().to_string(), v.trim().to_string())),
+                None => bail!("invalid WARC header line {:?}", line),
+            }
+        }
+        let mut record = WarcRecord {
+            version,
+            headers,
+            content: Vec::new(),
+        };
+        let len = record
+            .header("Content-Length")
+            .ok_or_else(|| anyhow!("WARC record is missing Content-Length"))?
+            .parse::<u64>()
+            .context("invalid Content-Length in WARC record")?;
+        let read = (&mut self.inner)
+            .take(len)
+            .read_to_end(&mut record.content)?;
+        if (read as u64) < len {
+            bail!("unexpected end of file in WARC record content");
+        }
+        Ok(Some(record))
+    }
+}
+
+impl<R: BufRead> Iterator for WarcReader<R> {
+    type Item = Result<WarcRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.read_record().transpose();
+        // we can't resync after a broken record so stop at the first error
+        if let None | Some(Err(_)) = res {
+            self.done = true;
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(kind: &str, content: &str) -> String {
+        format!(
+            "WARC/1.0\r\nWARC-Type: {}\r\nWARC-Target-URI: <https://parler.com/post/abc>\r\nWARC-Date: 2021-01-10T12:00:00Z\r\nWARC-Record-ID: <urn:uuid:1234>\r\nContent-Length: {}\r\n\r\n{}\r\n\r\n",
+            kind,
+            content.len(),
+            content
+        )
+    }
+
+    #[test]
+    fn decodes_compressed_body() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"<html></html>").unwrap();
+        let mut http =
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Encoding: gzip\r\n\r\n"
+                .to_vec();
+        http.extend(encoder.finish().unwrap());
+        let mut data = format!(
+            "WARC/1.0\r\nWARC-Type: response\r\nContent-Length: {}\r\n\r\n",
+            http.len()
+        )
+        .into_bytes();
+        data.extend(&http);
+        data.extend(b"\r\n\r\n");
+
+        let gzipped = WarcReader::new(data.as_slice()).next().unwrap().unwrap();
+        let body = gzipped.http_response().unwrap().into_body().unwrap();
+        assert_eq!(body, b"<html></html>");
+
+        // unknown codings are an error rather than binary going into the html parser
+        let http = "HTTP/1.1 200 OK\r\nContent-Encoding: br\r\n\r\n<html>";
+        let data = record("response", http);
+        let brotli = WarcReader::new(data.as_bytes()).next().unwrap().unwrap();
+        assert!(brotli.http_response().unwrap().into_body().is_err());
+    }
+
+    #[test]
+    fn reads_records() {
+        let http = "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<html></html>";
+        let data = format!("{}{}", record("warcinfo", "software: test"), record("response", http));
+        let records = WarcReader::new(data.as_bytes())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(!records[0].is_response());
+        let response = &records[1];
+        assert!(response.is_response());
+        assert_eq!(response.target_uri(), Some("https://parler.com/post/abc"));
+        assert_eq!(response.date(), Some("2021-01-10T12:00:00Z"));
+        assert_eq!(response.record_id(), Some("<urn:uuid:1234>"));
+        let http = response.http_response().unwrap();
+        assert!(http.is_html());
+        assert_eq!(http.into_body().unwrap(), b"<html></html>");
+    }
+
+    #[test]
+    fn decodes_chunked_body() {
+        let http = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\n<html>\r\n7\r\n</html>\r\n0\r\n\r\n";
+        let data = record("response", http);
+        let record = WarcReader::new(data.as_bytes()).next().unwrap().unwrap();
+        let body = record.http_response().unwrap().into_body().unwrap();
+        assert_eq!(body, b"<html></html>");
+    }
+
+    #[test]
+    fn stops_after_error() {
+        let data = "WARC/1.0\r\nWARC-Type: response\r\n\r\n";
+        let mut reader = WarcReader::new(data.as_bytes());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
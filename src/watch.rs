@@ -0,0 +1,240 @@
+use crate::args::is_hidden;
+use crate::progress::Stderr;
+use crate::shutdown::Shutdown;
+use anyhow::{Context, Result};
+use crossbeam_channel::{unbounded, Receiver};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use walkdir::{DirEntry, WalkDir};
+
+// how often the shutdown is checked while nothing arrives
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A file below one of the watched roots along with its depth below that root. The entry
+/// doesn't come out of a walk of the root, so its own `depth()` doesn't say.
+pub type Arrival = walkdir::Result<(DirEntry, usize)>;
+
+struct Watched {
+    root: PathBuf,
+    dir: PathBuf,
+}
+
+/// Yields files as they're closed after writing or moved into the directories under `roots`,
+/// until the run is stopped. New directories are watched as well.
+pub struct Watcher {
+    inotify: Inotify,
+    dirs: HashMap<WatchDescriptor, Watched>,
+    pending: VecDeque<Arrival>,
+    buffer: Vec<u8>,
+    shutdown: Shutdown,
+    stderr: Stderr,
+}
+
+impl Watcher {
+    pub fn new(
+        roots: impl Iterator<Item = PathBuf>,
+        shutdown: Shutdown,
+        stderr: Stderr,
+    ) -> Result<Self> {
+        let mut watcher = Self {
+            inotify: Inotify::init().context("failed to initialize inotify")?,
+            dirs: HashMap::new(),
+            pending: VecDeque::new(),
+            buffer: vec![0; 64 * 1024],
+            shutdown,
+            stderr,
+        };
+        // errors are reported by the initial walk
+        for root in roots {
+            let dirs = WalkDir::new(&root)
+                .into_iter()
+                .filter_entry(|v| !is_hidden(v))
+                .filter_map(|v| v.ok())
+                .filter(|v| v.file_type().is_dir());
+            for dir in dirs {
+                watcher
+                    .add(&root, dir.path())
+                    .with_context(|| format!("failed to watch {}", dir.path().display()))?;
+            }
+        }
+        Ok(watcher)
+    }
+
+    /// Watches on a thread of its own, arrivals are sent to the returned channel until the
+    /// run stops
+    pub fn spawn(self) -> Receiver<Arrival> {
+        let (tx, rx) = unbounded();
+        std::thread::spawn(move || {
+            for arrival in self {
+                if tx.send(arrival).is_err() {
+                    return;
+                }
+            }
+        });
+        rx
+    }
+
+    fn add(&mut self, root: &Path, dir: &Path) -> io::Result<()> {
+        let wd = self.inotify.watches().add(
+            dir,
+            WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE,
+        )?;
+        self.dirs.insert(
+            wd,
+            Watched {
+                root: root.to_path_buf(),
+                dir: dir.to_path_buf(),
+            },
+        );
+        Ok(())
+    }
+
+    fn arrived(&mut self, root: &Path, path: &Path) {
+        let depth = path
+            .strip_prefix(root)
+            .map_or(0, |v| v.components().count());
+        let entry = match WalkDir::new(path).max_depth(0).into_iter().next() {
+            Some(Ok(v)) if is_hidden(&v) => return,
+            Some(v) => v,
+            None => return,
+        };
+        match entry {
+            Ok(v) if v.file_type().is_dir() => self.arrived_dir(root, &v, depth),
+            v => self.pending.push_back(v.map(|v| (v, depth))),
+        }
+    }
+
+    // A new directory may already have files in it by the time it's watched, those are
+    // picked up by walking it
+    fn arrived_dir(&mut self, root: &Path, dir: &DirEntry, depth: usize) {
+        self.watch(root, dir.path());
+        let entries = WalkDir::new(dir.path())
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|v| !is_hidden(v));
+        for entry in entries {
+            match entry {
+                Ok(v) if v.file_type().is_dir() => self.watch(root, v.path()),
+                v => self.pending.push_back(v.map(|v| {
+                    let below = v.depth();
+                    (v, depth + below)
+                })),
+            }
+        }
+    }
+
+    fn watch(&mut self, root: &Path, dir: &Path) {
+        if let Err(e) = self.add(root, dir) {
+            self.stderr
+                .print(format_args!("failed to watch {}: {}", dir.display(), e));
+        }
+    }
+
+    fn read_events(&mut self) -> io::Result<()> {
+        let events = match self.inotify.read_events(&mut self.buffer) {
+            Ok(v) => v,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(POLL_INTERVAL);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        let mut arrived = Vec::new();
+        for event in events {
+            if event.mask.contains(EventMask::Q_OVERFLOW) {
+                self.stderr
+                    .print("too many files arrived at once, some of them were missed");
+                continue;
+            }
+            if event.mask.contains(EventMask::IGNORED) {
+                self.dirs.remove(&event.wd);
+                continue;
+            }
+            let (watched, name) = match (self.dirs.get(&event.wd), event.name) {
+                (Some(watched), Some(name)) => (watched, name),
+                _ => continue,
+            };
+            // files count once they're complete, directories as soon as they exist
+            let complete = if event.mask.contains(EventMask::ISDIR) {
+                event
+                    .mask
+                    .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+            } else {
+                event
+                    .mask
+                    .intersects(EventMask::CLOSE_WRITE | EventMask::MOVED_TO)
+            };
+            if complete {
+                arrived.push((watched.root.clone(), watched.dir.join(name)));
+            }
+        }
+        for (root, path) in arrived {
+            self.arrived(&root, &path);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Watcher {
+    type Item = Arrival;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.shutdown.is_stopping() {
+                return None;
+            }
+            if let Some(v) = self.pending.pop_front() {
+                return Some(v);
+            }
+            if let Err(e) = self.read_events() {
+                self.stderr.print(format_args!("stopped watching: {}", e));
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn next_path(watcher: &mut Watcher) -> (PathBuf, usize) {
+        let (entry, depth) = watcher.next().unwrap().unwrap();
+        (entry.into_path(), depth)
+    }
+
+    #[test]
+    fn yields_new_files() {
+        let root = std::env::temp_dir().join(format!("parler-watch-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let shutdown = Shutdown::default();
+        let mut watcher = Watcher::new(
+            std::iter::once(root.clone()),
+            shutdown.clone(),
+            Stderr::default(),
+        )
+        .unwrap();
+
+        fs::write(root.join(".partial.html"), "hidden").unwrap();
+        fs::write(root.join("page.html"), "page").unwrap();
+        assert_eq!(next_path(&mut watcher), (root.join("page.html"), 1));
+
+        // the directory is walked when it shows up, later files come from its own watch
+        fs::create_dir_all(root.join("sub/.hidden")).unwrap();
+        fs::write(root.join("sub/.hidden/skipped.html"), "hidden").unwrap();
+        fs::write(root.join("sub/first.html"), "first").unwrap();
+        assert_eq!(next_path(&mut watcher), (root.join("sub/first.html"), 2));
+        fs::write(root.join("sub/second.html"), "second").unwrap();
+        assert_eq!(next_path(&mut watcher), (root.join("sub/second.html"), 2));
+
+        shutdown.cancel();
+        assert!(watcher.next().is_none());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
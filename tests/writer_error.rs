@@ -0,0 +1,41 @@
+use std::{
+    fs,
+    process::{Command, Stdio},
+};
+
+const EXAMPLE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/examples/echo--parent-no-comment.html"
+);
+
+// Once stdout is gone the writer fails on the first document, the run has to stop with an
+// error and nothing may be recorded as done
+#[test]
+fn stops_when_the_writer_fails() {
+    let dir = std::env::temp_dir().join(format!("parler-writer-error-{}", std::process::id()));
+    let pages = dir.join("pages");
+    fs::create_dir_all(&pages).unwrap();
+    for i in 0..200 {
+        fs::copy(EXAMPLE, pages.join(format!("page-{:03}.html", i))).unwrap();
+    }
+    let success_log = dir.join("success.log");
+
+    for ordered in &[false, true] {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_parler-indexer"));
+        command
+            .args(&["-r", "-j", "2", "--queue-size", "1", "--success-log"])
+            .arg(&success_log)
+            .arg(&pages)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        if *ordered {
+            command.arg("--ordered");
+        }
+        let mut child = command.spawn().unwrap();
+        drop(child.stdout.take());
+        let status = child.wait().unwrap();
+        assert_eq!(status.code(), Some(1), "ordered: {}", ordered);
+        assert_eq!(fs::read_to_string(&success_log).unwrap(), "", "ordered: {}", ordered);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}